@@ -0,0 +1,37 @@
+use super::WallpaperSetter;
+use crate::config::ImageMode;
+use crate::{ApplicationError, ApplicationResult};
+use std::process::Command;
+
+pub struct FehSetter {
+    pub mode: ImageMode,
+}
+
+impl FehSetter {
+    pub fn new(mode: ImageMode) -> FehSetter {
+        FehSetter { mode }
+    }
+}
+
+impl WallpaperSetter for FehSetter {
+    fn name(&self) -> &'static str {
+        "feh"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        let args: &[&str] = match self.mode {
+            ImageMode::Zoom => &["--bg-fill"],
+            ImageMode::Centered => &["--bg-center"],
+            ImageMode::Scaled => &["--bg-max"],
+            ImageMode::Spanned => &["--bg-fill", "--no-xinerama"],
+        };
+        let status = Command::new("feh").args(args).arg(path).status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(ApplicationError::BackendError {
+                e: format!("feh exited with {}", status),
+            })
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use super::WallpaperSetter;
+use crate::config::ImageMode;
+use crate::{ApplicationError, ApplicationResult};
+use std::process::Command;
+
+const BACKGROUND_SCHEMA: &str = "org.gnome.desktop.background";
+
+fn picture_options(mode: ImageMode) -> &'static str {
+    match mode {
+        ImageMode::Zoom => "zoom",
+        ImageMode::Centered => "centered",
+        ImageMode::Scaled => "scaled",
+        ImageMode::Spanned => "spanned",
+    }
+}
+
+pub struct GnomeSetter {
+    pub mode: ImageMode,
+}
+
+impl GnomeSetter {
+    pub fn new(mode: ImageMode) -> GnomeSetter {
+        GnomeSetter { mode }
+    }
+
+    fn gsettings_set(key: &str, value: &str) -> ApplicationResult<()> {
+        let output = Command::new("gsettings")
+            .args(["set", BACKGROUND_SCHEMA, key, value])
+            .output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(ApplicationError::BackendError {
+                e: format!(
+                    "gsettings set {} {} failed: {}",
+                    BACKGROUND_SCHEMA,
+                    key,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            })
+        }
+    }
+}
+
+pub fn file_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+impl WallpaperSetter for GnomeSetter {
+    fn name(&self) -> &'static str {
+        "gnome"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        let uri = file_uri(path);
+        Self::gsettings_set("picture-uri", &uri)?;
+        Self::gsettings_set("picture-uri-dark", &uri)?;
+        Self::gsettings_set("picture-options", picture_options(self.mode))
+    }
+}
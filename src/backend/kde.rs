@@ -0,0 +1,99 @@
+use super::gnome::file_uri;
+use super::WallpaperSetter;
+use crate::{ApplicationError, ApplicationResult};
+use std::process::Command;
+
+const PLASMA_SERVICE: &str = "org.kde.plasmashell";
+const PLASMA_PATH: &str = "/PlasmaShell";
+const PLASMA_EVALUATE_SCRIPT: &str = "org.kde.PlasmaShell.evaluateScript";
+
+pub struct KdeSetter {
+    // Only the containments on this screen are changed, every desktop otherwise
+    pub screen: Option<u32>,
+}
+
+impl KdeSetter {
+    pub fn new(screen: Option<u32>) -> KdeSetter {
+        KdeSetter { screen }
+    }
+
+    fn script(&self, path: &str) -> String {
+        // a JSON string literal is also a valid javascript one
+        let image = serde_json::Value::String(file_uri(path)).to_string();
+        let screen_filter = match self.screen {
+            Some(screen) => format!("if (d.screen != {}) continue;", screen),
+            None => String::new(),
+        };
+        format!(
+            "var all = desktops();\
+             for (var i = 0; i < all.length; i++) {{\
+                 var d = all[i];\
+                 {}\
+                 d.wallpaperPlugin = \"org.kde.image\";\
+                 d.currentConfigGroup = Array(\"Wallpaper\", \"org.kde.image\", \"General\");\
+                 d.writeConfig(\"Image\", {});\
+             }}",
+            screen_filter, image
+        )
+    }
+
+    fn evaluate_script(script: &str) -> ApplicationResult<()> {
+        let output = Command::new("dbus-send")
+            .args([
+                "--session",
+                "--print-reply",
+                &format!("--dest={}", PLASMA_SERVICE),
+                PLASMA_PATH,
+                PLASMA_EVALUATE_SCRIPT,
+                &format!("string:{}", script),
+            ])
+            .output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(ApplicationError::BackendError {
+                e: format!(
+                    "{} failed: {}",
+                    PLASMA_EVALUATE_SCRIPT,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            })
+        }
+    }
+}
+
+impl WallpaperSetter for KdeSetter {
+    fn name(&self) -> &'static str {
+        "kde"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        Self::evaluate_script(&self.script(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_changes_every_desktop_without_a_screen() {
+        let script = KdeSetter::new(None).script("/home/me/dune.png");
+        assert!(!script.contains("d.screen"));
+        assert!(script.contains("d.writeConfig(\"Image\", \"file:///home/me/dune.png\");"));
+    }
+
+    #[test]
+    fn script_filters_on_the_screen() {
+        let script = KdeSetter::new(Some(1)).script("/home/me/dune.png");
+        assert!(script.contains("if (d.screen != 1) continue;"));
+    }
+
+    #[test]
+    fn script_quotes_the_path() {
+        let script = KdeSetter::new(None).script("/home/me/\"quoted\" 'dune'\\.png");
+        assert!(script.contains(
+            "d.writeConfig(\"Image\", \"file:///home/me/%22quoted%22%20%27dune%27%5C.png\");"
+        ));
+    }
+}
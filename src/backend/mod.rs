@@ -0,0 +1,119 @@
+use crate::config::Config;
+use crate::{ApplicationError, ApplicationResult};
+#[cfg(not(windows))]
+use std::env;
+
+#[cfg(not(windows))]
+mod feh;
+#[cfg(not(windows))]
+mod gnome;
+#[cfg(not(windows))]
+mod kde;
+mod record;
+#[cfg(not(windows))]
+mod wayland;
+#[cfg(windows)]
+mod windows;
+#[cfg(not(windows))]
+mod x11;
+
+#[cfg(windows)]
+pub use self::windows::WindowsSetter;
+#[cfg(not(windows))]
+pub use feh::FehSetter;
+#[cfg(not(windows))]
+pub use gnome::GnomeSetter;
+#[cfg(not(windows))]
+pub use kde::KdeSetter;
+pub use record::RecordingSetter;
+#[cfg(not(windows))]
+pub use wayland::{HyprpaperSetter, SwaybgSetter, SwwwSetter};
+#[cfg(not(windows))]
+pub use x11::X11Setter;
+
+pub trait WallpaperSetter: Send {
+    fn name(&self) -> &'static str;
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()>;
+}
+
+pub fn is_available(name: &str) -> bool {
+    match name {
+        #[cfg(windows)]
+        "windows" => true,
+        #[cfg(not(windows))]
+        "feh" | "gnome" | "kde" | "swww" | "hyprpaper" | "swaybg" | "x11" => true,
+        "record" => true,
+        _ => false,
+    }
+}
+
+pub fn from_name(name: &str, config: &Config) -> ApplicationResult<Box<dyn WallpaperSetter>> {
+    let backend = &config.backend;
+    let mode = config.image.mode;
+    match name {
+        #[cfg(windows)]
+        "windows" => Ok(Box::new(WindowsSetter)),
+        #[cfg(not(windows))]
+        "feh" => Ok(Box::new(FehSetter::new(mode))),
+        #[cfg(not(windows))]
+        "gnome" => Ok(Box::new(GnomeSetter::new(mode))),
+        #[cfg(not(windows))]
+        "kde" => Ok(Box::new(KdeSetter::new(backend.screen))),
+        #[cfg(not(windows))]
+        "swww" => Ok(Box::new(SwwwSetter::new(backend.swww.clone(), mode))),
+        #[cfg(not(windows))]
+        "hyprpaper" => Ok(Box::new(HyprpaperSetter::new(
+            match &backend.hyprpaper_socket {
+                Some(socket) => socket.into(),
+                None => HyprpaperSetter::default_socket()?,
+            },
+            backend.monitor.clone(),
+        ))),
+        #[cfg(not(windows))]
+        "swaybg" => Ok(Box::new(SwaybgSetter::new(mode))),
+        #[cfg(not(windows))]
+        "x11" => Ok(Box::new(X11Setter::new(mode))),
+        "record" => Ok(Box::new(RecordingSetter::new(backend.record_file.clone()))),
+        _ => Err(ApplicationError::BackendError {
+            e: format!("Unknown wallpaper backend '{}'", name),
+        }),
+    }
+}
+
+#[cfg(windows)]
+fn detect(config: &Config) -> ApplicationResult<Box<dyn WallpaperSetter>> {
+    from_name("windows", config)
+}
+
+#[cfg(not(windows))]
+fn detect(config: &Config) -> ApplicationResult<Box<dyn WallpaperSetter>> {
+    let desktop = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    let is_desktop = |name: &str| desktop.split(':').any(|d| d.eq_ignore_ascii_case(name));
+    let name = if is_desktop("gnome") {
+        "gnome"
+    } else if is_desktop("kde") {
+        "kde"
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        if is_desktop("hyprland") {
+            "hyprpaper"
+        } else if is_desktop("sway") {
+            "swaybg"
+        } else {
+            "swww"
+        }
+    } else if env::var_os("DISPLAY").is_some() {
+        "x11"
+    } else {
+        "feh"
+    };
+    from_name(name, config)
+}
+
+pub fn select(config: &Config) -> ApplicationResult<Box<dyn WallpaperSetter>> {
+    let setter = match &config.backend.name {
+        Some(name) => from_name(name, config)?,
+        None => detect(config)?,
+    };
+    log::trace!("Using wallpaper backend '{}'", setter.name());
+    Ok(setter)
+}
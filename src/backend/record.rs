@@ -0,0 +1,34 @@
+use super::WallpaperSetter;
+use crate::ApplicationResult;
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+
+// Doesn't touch the desktop: every applied path is logged and optionally
+// appended to a file, so the rotation can be exercised headless.
+pub struct RecordingSetter {
+    record_file: Option<String>,
+}
+
+impl RecordingSetter {
+    pub fn new(record_file: Option<String>) -> RecordingSetter {
+        RecordingSetter { record_file }
+    }
+}
+
+impl WallpaperSetter for RecordingSetter {
+    fn name(&self) -> &'static str {
+        "record"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        if let Some(record_file) = &self.record_file {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(record_file)?;
+            writeln!(file, "{}", path)?;
+        }
+        log::info!("Recorded wallpaper '{}'", path);
+        Ok(())
+    }
+}
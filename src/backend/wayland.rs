@@ -0,0 +1,226 @@
+use super::WallpaperSetter;
+use crate::config::{ImageMode, SwwwConfig};
+use crate::{ApplicationError, ApplicationResult};
+use std::env;
+use std::io::prelude::*;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct SwwwSetter {
+    pub options: SwwwConfig,
+    pub mode: ImageMode,
+}
+
+impl SwwwSetter {
+    pub fn new(options: SwwwConfig, mode: ImageMode) -> SwwwSetter {
+        SwwwSetter { options, mode }
+    }
+}
+
+impl WallpaperSetter for SwwwSetter {
+    fn name(&self) -> &'static str {
+        "swww"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        let resize = match self.mode {
+            ImageMode::Zoom | ImageMode::Spanned => "crop",
+            ImageMode::Scaled => "fit",
+            ImageMode::Centered => "no",
+        };
+        let output = Command::new("swww")
+            .arg("img")
+            .arg(path)
+            .args(["--resize", resize])
+            .args(["--transition-type", &self.options.transition])
+            .args(["--transition-duration", &self.options.duration.to_string()])
+            .output()?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(ApplicationError::BackendError {
+                e: format!(
+                    "swww img failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            })
+        }
+    }
+}
+
+pub struct HyprpaperSetter {
+    pub socket: PathBuf,
+    // An empty monitor applies the wallpaper to every output
+    pub monitor: String,
+}
+
+impl HyprpaperSetter {
+    pub fn new(socket: PathBuf, monitor: String) -> HyprpaperSetter {
+        HyprpaperSetter { socket, monitor }
+    }
+
+    pub fn default_socket() -> ApplicationResult<PathBuf> {
+        let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE").map_err(|_| {
+            ApplicationError::BackendError {
+                e: "HYPRLAND_INSTANCE_SIGNATURE is not set".to_owned(),
+            }
+        })?;
+        let runtime_dir = env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_owned());
+        let socket = PathBuf::from(runtime_dir)
+            .join("hypr")
+            .join(&signature)
+            .join(".hyprpaper.sock");
+        if socket.exists() {
+            Ok(socket)
+        } else {
+            // hyprpaper before 0.6 kept its socket in /tmp
+            Ok(PathBuf::from("/tmp/hypr")
+                .join(&signature)
+                .join(".hyprpaper.sock"))
+        }
+    }
+
+    fn request(&self, command: &str) -> ApplicationResult<()> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        stream.write_all(command.as_bytes())?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply)?;
+        if reply.trim() == "ok" {
+            Ok(())
+        } else {
+            Err(ApplicationError::BackendError {
+                e: format!("hyprpaper '{}' failed: {}", command, reply.trim()),
+            })
+        }
+    }
+}
+
+impl WallpaperSetter for HyprpaperSetter {
+    fn name(&self) -> &'static str {
+        "hyprpaper"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        self.request(&format!("preload {}", path))?;
+        self.request(&format!("wallpaper {},{}", self.monitor, path))?;
+        self.request("unload unused")
+    }
+}
+
+// How long a new swaybg instance runs next to the previous one
+const SWAYBG_STARTUP: Duration = Duration::from_millis(300);
+
+pub struct SwaybgSetter {
+    pub mode: ImageMode,
+    child: Option<Child>,
+}
+
+impl SwaybgSetter {
+    pub fn new(mode: ImageMode) -> SwaybgSetter {
+        SwaybgSetter { mode, child: None }
+    }
+}
+
+impl WallpaperSetter for SwaybgSetter {
+    fn name(&self) -> &'static str {
+        "swaybg"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        let mode = match self.mode {
+            ImageMode::Zoom | ImageMode::Spanned => "fill",
+            ImageMode::Scaled => "fit",
+            ImageMode::Centered => "center",
+        };
+        // start the new instance first so the desktop never flashes empty
+        let mut child = Command::new("swaybg")
+            .args(["-i", path, "-m", mode])
+            .stdin(Stdio::null())
+            .spawn()?;
+        // swaybg can't tell when it has drawn, the old instance is only
+        // stopped once the new one has had the time to
+        let started = Instant::now();
+        while started.elapsed() < SWAYBG_STARTUP {
+            if let Some(status) = child.try_wait()? {
+                return Err(ApplicationError::BackendError {
+                    e: format!("swaybg exited at once with {}", status),
+                });
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        if let Some(mut previous) = self.child.replace(child) {
+            // it may have died on its own already
+            let _ = previous.kill();
+            previous.wait()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    // Answers each request with the next reply and returns the requests
+    fn fake_hyprpaper(
+        socket: &std::path::Path,
+        replies: &[&str],
+    ) -> thread::JoinHandle<Vec<String>> {
+        let listener = UnixListener::bind(socket).unwrap();
+        let replies = replies
+            .iter()
+            .map(|reply| reply.to_string())
+            .collect::<Vec<_>>();
+        thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                stream.read_to_string(&mut request).unwrap();
+                requests.push(request);
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            requests
+        })
+    }
+
+    #[test]
+    fn hyprpaper_preloads_sets_and_unloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join(".hyprpaper.sock");
+        let server = fake_hyprpaper(&socket, &["ok", "ok", "ok"]);
+
+        HyprpaperSetter::new(socket, "DP-1".to_owned())
+            .set_wallpaper("/home/me/dune.png")
+            .unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "preload /home/me/dune.png",
+                "wallpaper DP-1,/home/me/dune.png",
+                "unload unused",
+            ]
+        );
+    }
+
+    #[test]
+    fn hyprpaper_reports_a_refused_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join(".hyprpaper.sock");
+        let server = fake_hyprpaper(&socket, &["ok", "wallpaper failed (not preloaded)"]);
+
+        let result = HyprpaperSetter::new(socket, String::new()).set_wallpaper("/dune.png");
+
+        assert_eq!(server.join().unwrap()[1], "wallpaper ,/dune.png");
+        match result {
+            Err(ApplicationError::BackendError { e }) => assert!(e.contains("not preloaded")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use super::WallpaperSetter;
+use crate::{ApplicationError, ApplicationResult};
+use ::windows::Win32::Foundation::BOOL;
+use ::windows::Win32::UI::WindowsAndMessaging::{
+    SystemParametersInfoW, SPIF_SENDCHANGE, SPIF_UPDATEINIFILE, SPI_SETDESKWALLPAPER,
+};
+use std::ffi::c_void;
+use std::ffi::OsStr;
+use std::os::windows::prelude::OsStrExt;
+
+pub struct WindowsSetter;
+
+impl WallpaperSetter for WindowsSetter {
+    fn name(&self) -> &'static str {
+        "windows"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        let mut path: Vec<u16> = OsStr::new(path).encode_wide().collect();
+        // append null byte
+        path.push(0);
+
+        let successful = unsafe {
+            SystemParametersInfoW(
+                SPI_SETDESKWALLPAPER,
+                0,
+                Some(path.as_ptr() as *mut c_void),
+                SPIF_UPDATEINIFILE | SPIF_SENDCHANGE,
+            ) != BOOL(0)
+        };
+
+        if successful {
+            Ok(())
+        } else {
+            Err(ApplicationError::WindowsOSError {
+                e: format!(
+                    "SystemParametersInfoW failed: {}",
+                    std::io::Error::last_os_error()
+                ),
+            })
+        }
+    }
+}
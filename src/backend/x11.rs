@@ -0,0 +1,285 @@
+use super::WallpaperSetter;
+use crate::config::ImageMode;
+use crate::{ApplicationError, ApplicationResult};
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+use std::fmt::Display;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    AtomEnum, ChangeWindowAttributesAux, CloseDown, ConnectionExt, CreateGCAux, ImageFormat,
+    ImageOrder, PropMode, Screen,
+};
+use x11rb::wrapper::ConnectionExt as _;
+
+// Paints the root window directly, the way `feh --bg-fill` or `Esetroot` do,
+// and advertises the pixmap so pseudo-transparent clients can find it.
+pub struct X11Setter {
+    pub mode: ImageMode,
+}
+
+impl X11Setter {
+    pub fn new(mode: ImageMode) -> X11Setter {
+        X11Setter { mode }
+    }
+}
+
+fn x11_error<E: Display>(err: E) -> ApplicationError {
+    ApplicationError::BackendError {
+        e: format!("X11: {}", err),
+    }
+}
+
+// Scales the image to the screen and converts it to 32 bits per pixel
+// Z-format data in the server byte order.
+fn render(
+    path: &str,
+    mode: ImageMode,
+    screen: &Screen,
+    byte_order: ImageOrder,
+) -> ApplicationResult<Vec<u8>> {
+    let width = u32::from(screen.width_in_pixels);
+    let height = u32::from(screen.height_in_pixels);
+    let source = image::open(path).map_err(|e| ApplicationError::BackendError {
+        e: format!("Cannot decode '{}': {}", path, e),
+    })?;
+    // the root window already spans every monitor
+    let scaled = match mode {
+        ImageMode::Zoom | ImageMode::Spanned => {
+            source.resize_to_fill(width, height, FilterType::Triangle)
+        }
+        ImageMode::Scaled => source.resize(width, height, FilterType::Triangle),
+        ImageMode::Centered => source,
+    }
+    .to_rgb8();
+    let mut image = RgbImage::new(width, height);
+    imageops::overlay(
+        &mut image,
+        &scaled,
+        (i64::from(width) - i64::from(scaled.width())) / 2,
+        (i64::from(height) - i64::from(scaled.height())) / 2,
+    );
+
+    let mut data = Vec::with_capacity((width * height * 4) as usize);
+    for pixel in image.pixels() {
+        let [r, g, b] = pixel.0;
+        if byte_order == ImageOrder::LSB_FIRST {
+            data.extend_from_slice(&[b, g, r, 0]);
+        } else {
+            data.extend_from_slice(&[0, r, g, b]);
+        }
+    }
+    Ok(data)
+}
+
+impl WallpaperSetter for X11Setter {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn set_wallpaper(&mut self, path: &str) -> ApplicationResult<()> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x11_error)?;
+        let setup = conn.setup();
+        let screen = &setup.roots[screen_num];
+        let root = screen.root;
+        let depth = screen.root_depth;
+
+        if !setup
+            .pixmap_formats
+            .iter()
+            .any(|format| format.depth == depth && format.bits_per_pixel == 32)
+        {
+            return Err(ApplicationError::BackendError {
+                e: format!("Unsupported root window depth {}", depth),
+            });
+        }
+        let data = render(path, self.mode, screen, setup.image_byte_order)?;
+
+        let pixmap = conn.generate_id().map_err(x11_error)?;
+        conn.create_pixmap(
+            depth,
+            pixmap,
+            root,
+            screen.width_in_pixels,
+            screen.height_in_pixels,
+        )
+        .map_err(x11_error)?;
+        let gc = conn.generate_id().map_err(x11_error)?;
+        conn.create_gc(gc, pixmap, &CreateGCAux::new())
+            .map_err(x11_error)?;
+
+        // a whole screen doesn't fit in a single request, upload it in bands
+        let stride = usize::from(screen.width_in_pixels) * 4;
+        let rows_per_request = ((conn.maximum_request_bytes() - 64) / stride).max(1);
+        for (band, chunk) in data.chunks(rows_per_request * stride).enumerate() {
+            conn.put_image(
+                ImageFormat::Z_PIXMAP,
+                pixmap,
+                gc,
+                screen.width_in_pixels,
+                (chunk.len() / stride) as u16,
+                0,
+                (band * rows_per_request) as i16,
+                0,
+                depth,
+                chunk,
+            )
+            .map_err(x11_error)?;
+        }
+        conn.free_gc(gc).map_err(x11_error)?;
+
+        let xrootpmap = conn
+            .intern_atom(false, b"_XROOTPMAP_ID")
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?
+            .atom;
+        let esetroot = conn
+            .intern_atom(false, b"ESETROOT_PMAP_ID")
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?
+            .atom;
+
+        // free the pixmap retained by the previous setter, if it still matches
+        let previous = |atom| -> ApplicationResult<Option<u32>> {
+            Ok(conn
+                .get_property(false, root, atom, AtomEnum::PIXMAP, 0, 1)
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?
+                .value32()
+                .and_then(|mut value| value.next()))
+        };
+        let old_esetroot = previous(esetroot)?;
+        if old_esetroot.is_some() && old_esetroot == previous(xrootpmap)? {
+            if let Some(old) = old_esetroot {
+                conn.kill_client(old).map_err(x11_error)?;
+            }
+        }
+
+        for atom in [xrootpmap, esetroot] {
+            conn.change_property32(PropMode::REPLACE, root, atom, AtomEnum::PIXMAP, &[pixmap])
+                .map_err(x11_error)?;
+        }
+        conn.change_window_attributes(
+            root,
+            &ChangeWindowAttributesAux::new().background_pixmap(pixmap),
+        )
+        .map_err(x11_error)?;
+        conn.clear_area(false, root, 0, 0, 0, 0)
+            .map_err(x11_error)?;
+        // keep the pixmap alive after we disconnect
+        conn.set_close_down_mode(CloseDown::RETAIN_PERMANENT)
+            .map_err(x11_error)?;
+        conn.sync().map_err(x11_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    const RED: [u8; 4] = [0, 0, 255, 0];
+    const BLACK: [u8; 4] = [0, 0, 0, 0];
+
+    fn screen(width: u16, height: u16) -> Screen {
+        Screen {
+            width_in_pixels: width,
+            height_in_pixels: height,
+            ..Screen::default()
+        }
+    }
+
+    fn render_solid(
+        width: u32,
+        height: u32,
+        color: [u8; 3],
+        mode: ImageMode,
+        byte_order: ImageOrder,
+    ) -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("solid.png");
+        RgbImage::from_pixel(width, height, Rgb(color))
+            .save(&path)
+            .unwrap();
+        render(&path.to_string_lossy(), mode, &screen(4, 4), byte_order).unwrap()
+    }
+
+    // rows of the 4x4 screen, a letter per pixel
+    fn picture(data: &[u8]) -> Vec<String> {
+        data.chunks(16)
+            .map(|row| {
+                row.chunks(4)
+                    .map(|pixel| match pixel {
+                        p if p == RED => 'r',
+                        p if p == BLACK => '.',
+                        _ => '?',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zoom_and_spanned_cover_the_screen() {
+        for mode in [ImageMode::Zoom, ImageMode::Spanned] {
+            let data = render_solid(8, 2, [255, 0, 0], mode, ImageOrder::LSB_FIRST);
+            assert_eq!(picture(&data), ["rrrr", "rrrr", "rrrr", "rrrr"]);
+        }
+    }
+
+    #[test]
+    fn scaled_keeps_the_whole_image() {
+        let data = render_solid(8, 4, [255, 0, 0], ImageMode::Scaled, ImageOrder::LSB_FIRST);
+        assert_eq!(picture(&data), ["....", "rrrr", "rrrr", "...."]);
+    }
+
+    #[test]
+    fn centered_is_not_scaled() {
+        let data = render_solid(
+            2,
+            2,
+            [255, 0, 0],
+            ImageMode::Centered,
+            ImageOrder::LSB_FIRST,
+        );
+        assert_eq!(picture(&data), ["....", ".rr.", ".rr.", "...."]);
+    }
+
+    #[test]
+    fn pixels_follow_the_server_byte_order() {
+        let lsb = render_solid(
+            4,
+            4,
+            [10, 20, 30],
+            ImageMode::Centered,
+            ImageOrder::LSB_FIRST,
+        );
+        assert_eq!(lsb.len(), 4 * 4 * 4);
+        assert_eq!(lsb[..4], [30, 20, 10, 0]);
+        let msb = render_solid(
+            4,
+            4,
+            [10, 20, 30],
+            ImageMode::Centered,
+            ImageOrder::MSB_FIRST,
+        );
+        assert_eq!(msb[..4], [0, 10, 20, 30]);
+    }
+
+    #[test]
+    fn undecodable_files_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("broken.png");
+        std::fs::write(&path, b"not an image").unwrap();
+        let result = render(
+            &path.to_string_lossy(),
+            ImageMode::Zoom,
+            &screen(4, 4),
+            ImageOrder::LSB_FIRST,
+        );
+        assert!(matches!(result, Err(ApplicationError::BackendError { .. })));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::ipc::Request;
+use clap::{Parser, Subcommand};
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about = "Rotates the desktop wallpaper")]
+pub struct Cli {
+    /// Configuration file to use instead of the default one
+    #[arg(short, long, global = true)]
+    pub config: Option<String>,
+
+    /// Minutes between two wallpaper changes
+    #[arg(short, long, global = true)]
+    pub interval: Option<u64>,
+
+    /// Directory where the wallpapers are downloaded
+    #[arg(short, long, global = true)]
+    pub directory: Option<String>,
+
+    /// Wallpaper backend, detected from the desktop if not given
+    #[arg(short, long, global = true)]
+    pub backend: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Keep changing the wallpaper (the default)
+    Daemon,
+    /// Change the wallpaper once and exit
+    Next,
+    /// Use a local image as wallpaper
+    Set { path: String },
+    /// Download a wallpaper without applying it
+    Fetch {
+        /// Index of the wallpaper in the source
+        #[arg(long)]
+        id: u32,
+    },
+    /// List the downloaded wallpapers
+    ListCache,
+    /// Show the current wallpaper
+    Info,
+    /// List the wallpapers applied before, the most recent first
+    History {
+        /// How many wallpapers to list
+        #[arg(short, long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Show how often each source was used
+    Stats,
+    /// Control a running daemon
+    Ctl {
+        #[command(subcommand)]
+        request: Request,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Validate the configuration and print the effective values
+    Check,
+}
@@ -0,0 +1,196 @@
+use crate::backend::{self, WallpaperSetter};
+use crate::config::{Config, SourceConfig};
+use crate::history::History;
+use crate::ipc::{self, Request};
+use crate::source::{self, Wallpaper};
+use crate::state;
+use crate::{ApplicationError, ApplicationResult};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn apply(setter: &mut dyn WallpaperSetter, wallpaper: &Wallpaper) -> ApplicationResult<()> {
+    setter.set_wallpaper(&wallpaper.path)?;
+    if let Err(e) = state::save_current(wallpaper) {
+        log::warn!("Cannot remember the current wallpaper: {}", e);
+    }
+    if let Err(e) = History::open().and_then(|history| history.record(wallpaper)) {
+        log::warn!("Cannot add the wallpaper to the history: {}", e);
+    }
+    Ok(())
+}
+
+pub async fn next(config: &Config) -> ApplicationResult<()> {
+    let mut setter = backend::select(config)?;
+    let source = source::from_sources(&config.sources, config.seed).await;
+    let wallpaper = source.fetch_random(&config.download_directory()?).await?;
+    apply(setter.as_mut(), &wallpaper)?;
+    println!("{}", wallpaper.path);
+    Ok(())
+}
+
+pub fn set(config: &Config, path: &str) -> ApplicationResult<()> {
+    let path = fs::canonicalize(path)?;
+    let wallpaper = Wallpaper {
+        path: path.to_string_lossy().into_owned(),
+        ..Wallpaper::default()
+    };
+    apply(backend::select(config)?.as_mut(), &wallpaper)
+}
+
+pub async fn fetch(config: &Config, id: u32) -> ApplicationResult<()> {
+    let source = source::from_sources(&config.sources, config.seed).await;
+    let count = source.count().await?;
+    if id >= count {
+        return Err(ApplicationError::ApiError {
+            e: format!("Wallpaper {} doesn't exist, the source has {}", id, count),
+        });
+    }
+    let wallpaper = source.fetch(id, &config.download_directory()?).await?;
+    println!("{}", wallpaper.path);
+    Ok(())
+}
+
+// The images downloaded by the configured sources
+pub fn cached_wallpapers(config: &Config) -> ApplicationResult<Vec<PathBuf>> {
+    let directory = config.download_directory()?;
+    let mut cached = Vec::new();
+    for source in &config.sources {
+        // nothing is downloaded for these
+        if let SourceConfig::Local(_) = source.source {
+            continue;
+        }
+        let folder = Path::new(&directory).join(source.source.folder());
+        let mut files = match fs::read_dir(&folder) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                // metadata sidecars and downloads in progress
+                .filter(|path| {
+                    path.extension().is_none_or(|extension| {
+                        extension != "json" && extension != &source::PARTIAL_SUFFIX[1..]
+                    })
+                })
+                .collect::<Vec<_>>(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        files.sort();
+        cached.append(&mut files);
+    }
+    Ok(cached)
+}
+
+pub fn list_cache(config: &Config) -> ApplicationResult<()> {
+    for file in cached_wallpapers(config)? {
+        println!("{}", file.display());
+    }
+    Ok(())
+}
+
+pub fn info() -> ApplicationResult<()> {
+    let current = match state::load_current()? {
+        Some(current) => current,
+        None => {
+            println!("No wallpaper has been applied yet");
+            return Ok(());
+        }
+    };
+    let wallpaper = &current.wallpaper;
+    println!("path:       {}", wallpaper.path);
+    if let Some(title) = &wallpaper.title {
+        println!("title:      {}", title);
+    }
+    if let Some(creator) = &wallpaper.creator {
+        println!("creator:    {}", creator);
+    }
+    if let Some(permalink) = &wallpaper.permalink {
+        println!("permalink:  {}", permalink);
+    }
+    if let Some(url) = &wallpaper.url {
+        println!("url:        {}", url);
+    }
+    if let Some(attribution) = &wallpaper.attribution {
+        println!("credit:     {}", attribution);
+    }
+    println!(
+        "applied:    {} minutes ago",
+        state::now().saturating_sub(current.applied_at) / 60
+    );
+    Ok(())
+}
+
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds / 60 % 60),
+    }
+}
+
+pub fn history(limit: u32) -> ApplicationResult<()> {
+    let now = state::now();
+    for entry in History::open()?.recent(limit)? {
+        let wallpaper = &entry.wallpaper;
+        println!(
+            "{:>8} ago  {:>8}  {:<14}  {}",
+            format_duration(now.saturating_sub(entry.applied_at)),
+            entry.duration.map_or("current".to_owned(), format_duration),
+            wallpaper.source.as_deref().unwrap_or("local"),
+            wallpaper.title.as_deref().unwrap_or(&wallpaper.path)
+        );
+    }
+    Ok(())
+}
+
+pub fn stats() -> ApplicationResult<()> {
+    let history = History::open()?;
+    let stats = history.stats()?;
+    if stats.is_empty() {
+        println!("No wallpaper has been applied yet");
+        return Ok(());
+    }
+    println!(
+        "{:<14}  {:>6}  {:>8}  {:>8}",
+        "source", "shown", "distinct", "time"
+    );
+    for source in &stats {
+        println!(
+            "{:<14}  {:>6}  {:>8}  {:>8}",
+            source.source,
+            source.shown,
+            source.distinct,
+            format_duration(source.duration)
+        );
+    }
+    println!();
+    println!("Shown the most:");
+    for (entry, shown) in history.most_shown(5)? {
+        let wallpaper = &entry.wallpaper;
+        println!(
+            "{:>6}  {}",
+            shown,
+            wallpaper.title.as_deref().unwrap_or(&wallpaper.path)
+        );
+    }
+    Ok(())
+}
+
+pub fn config_check(path: &str, config: &Config) -> ApplicationResult<()> {
+    let effective = toml::to_string_pretty(&config.redacted())
+        .map_err(|e| ApplicationError::ConfigError { e: e.to_string() })?;
+    println!("# '{}' is valid, effective configuration:", path);
+    print!("{}", effective);
+    Ok(())
+}
+
+pub async fn ctl(config: &Config, request: &Request) -> ApplicationResult<()> {
+    let response = ipc::send(&config.socket_path()?, request).await?;
+    if let Some(error) = response.error {
+        return Err(ApplicationError::ApiError { e: error });
+    }
+    if let Some(status) = response.status {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    }
+    Ok(())
+}
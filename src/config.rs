@@ -0,0 +1,953 @@
+use crate::backend;
+use crate::cli::Cli;
+use crate::dirs;
+use crate::ipc;
+use crate::source;
+use crate::{ApplicationError, ApplicationResult};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::str::FromStr;
+
+pub const CONFIG_FILENAME: &str = "config.toml";
+// A year, anything longer is surely a typo and would overflow the timers
+pub const MAX_INTERVAL: u64 = 365 * 24 * 60;
+
+const REDACTED: &str = "<redacted>";
+
+pub const SWWW_TRANSITIONS: &[&str] = &[
+    "none", "simple", "fade", "left", "right", "top", "bottom", "wipe", "wave", "grow", "center",
+    "any", "outer", "random",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // minutes between two wallpaper changes
+    pub interval: u64,
+    // where the wallpapers are downloaded, the pictures directory if unset
+    pub directory: Option<String>,
+    // control socket of the daemon, a named pipe on Windows
+    pub socket: Option<String>,
+    pub sources: Vec<WeightedSource>,
+    // makes the shuffled order of the sources reproducible, random if unset
+    pub seed: Option<u64>,
+    pub backend: BackendConfig,
+    pub image: ImageConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            interval: 60,
+            directory: None,
+            socket: None,
+            sources: vec![WeightedSource::default()],
+            seed: None,
+            backend: BackendConfig::default(),
+            image: ImageConfig::default(),
+        }
+    }
+}
+
+// A source with how often it is picked compared to the others
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeightedSource {
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(flatten)]
+    pub source: SourceConfig,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl Default for WeightedSource {
+    fn default() -> Self {
+        WeightedSource {
+            weight: default_weight(),
+            source: SourceConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SourceConfig {
+    SimpleDesktops {
+        // subdirectory of the download directory
+        #[serde(default = "default_simpledesktops_folder")]
+        folder: String,
+        #[serde(default = "default_simpledesktops_url")]
+        url: String,
+    },
+    Unsplash(UnsplashConfig),
+    Bing(BingConfig),
+    Apod(ApodConfig),
+    Wallhaven(WallhavenConfig),
+    Reddit(RedditConfig),
+    Local(LocalConfig),
+    Json(JsonConfig),
+    Feed(FeedConfig),
+    // solid color images generated locally, for testing
+    Fake {
+        #[serde(default = "default_fake_folder")]
+        folder: String,
+        #[serde(default = "default_fake_count")]
+        count: u32,
+        // every nth fetch fails
+        #[serde(default)]
+        fail_every: Option<u32>,
+    },
+}
+
+fn default_simpledesktops_folder() -> String {
+    "SimpleDesktop".to_owned()
+}
+
+fn default_simpledesktops_url() -> String {
+    source::URL_DESKTOP.to_owned()
+}
+
+fn default_fake_folder() -> String {
+    "Fake".to_owned()
+}
+
+fn default_fake_count() -> u32 {
+    10
+}
+
+impl SourceConfig {
+    pub fn folder(&self) -> &str {
+        match self {
+            SourceConfig::SimpleDesktops { folder, .. } => folder,
+            SourceConfig::Fake { folder, .. } => folder,
+            SourceConfig::Unsplash(unsplash) => &unsplash.folder,
+            SourceConfig::Bing(bing) => &bing.folder,
+            SourceConfig::Apod(apod) => &apod.folder,
+            SourceConfig::Wallhaven(wallhaven) => &wallhaven.folder,
+            SourceConfig::Reddit(reddit) => &reddit.folder,
+            // images are used in place
+            SourceConfig::Local(local) => &local.path,
+            SourceConfig::Json(json) => &json.folder,
+            SourceConfig::Feed(feed) => &feed.folder,
+        }
+    }
+
+    pub fn validate(&self) -> ApplicationResult<()> {
+        let invalid = |e: String| Err(ApplicationError::ConfigError { e });
+
+        match self {
+            SourceConfig::Unsplash(unsplash) => {
+                if unsplash.access_key.is_empty() {
+                    return invalid("unsplash needs an access_key".to_owned());
+                }
+                // the random endpoint refuses them together
+                if unsplash.query.is_some() && !unsplash.collections.is_empty() {
+                    return invalid("unsplash query and collections can't be combined".to_owned());
+                }
+                if let Some(orientation) = &unsplash.orientation {
+                    if !source::UNSPLASH_ORIENTATIONS.contains(&orientation.as_str()) {
+                        return invalid(format!("unknown unsplash orientation '{}'", orientation));
+                    }
+                }
+                Ok(())
+            }
+            SourceConfig::Bing(bing) => {
+                if !source::BING_RESOLUTIONS.contains(&bing.resolution.as_str()) {
+                    return invalid(format!("unknown bing resolution '{}'", bing.resolution));
+                }
+                if bing.days == 0 || bing.days > source::BING_MAX_DAYS {
+                    return invalid(format!(
+                        "bing days must be between 1 and {}",
+                        source::BING_MAX_DAYS
+                    ));
+                }
+                Ok(())
+            }
+            SourceConfig::Apod(apod) => {
+                if apod.api_key.is_empty() {
+                    return invalid("apod needs an api_key".to_owned());
+                }
+                let start = source::parse_apod_date(
+                    apod.start_date
+                        .as_deref()
+                        .unwrap_or(source::APOD_FIRST_DATE),
+                )?;
+                if let Some(end_date) = &apod.end_date {
+                    if source::parse_apod_date(end_date)? < start {
+                        return invalid("apod end_date is before start_date".to_owned());
+                    }
+                }
+                Ok(())
+            }
+            SourceConfig::Wallhaven(wallhaven) => {
+                let unknown = |values: &[String], known: &[&str]| {
+                    values
+                        .iter()
+                        .find(|value| !known.contains(&value.as_str()))
+                        .cloned()
+                };
+                if let Some(category) = unknown(&wallhaven.categories, source::WALLHAVEN_CATEGORIES)
+                {
+                    return invalid(format!("unknown wallhaven category '{}'", category));
+                }
+                if let Some(purity) = unknown(&wallhaven.purity, source::WALLHAVEN_PURITIES) {
+                    return invalid(format!("unknown wallhaven purity '{}'", purity));
+                }
+                if wallhaven.purity.iter().any(|purity| purity == "nsfw")
+                    && wallhaven.api_key.is_none()
+                {
+                    return invalid("wallhaven nsfw purity needs an api_key".to_owned());
+                }
+                if !source::WALLHAVEN_SORTINGS.contains(&wallhaven.sorting.as_str()) {
+                    return invalid(format!("unknown wallhaven sorting '{}'", wallhaven.sorting));
+                }
+                if !source::WALLHAVEN_TOP_RANGES.contains(&wallhaven.top_range.as_str()) {
+                    return invalid(format!(
+                        "unknown wallhaven top_range '{}'",
+                        wallhaven.top_range
+                    ));
+                }
+                for size in wallhaven.ratios.iter().chain(&wallhaven.atleast) {
+                    if parse_size(size).is_none() {
+                        return invalid(format!("invalid wallhaven size or ratio '{}'", size));
+                    }
+                }
+                Ok(())
+            }
+            SourceConfig::Reddit(reddit) => {
+                if reddit.subreddits.is_empty() {
+                    return invalid("reddit needs at least one subreddit".to_owned());
+                }
+                if !source::REDDIT_LISTINGS.contains(&reddit.listing.as_str()) {
+                    return invalid(format!("unknown reddit listing '{}'", reddit.listing));
+                }
+                if !source::REDDIT_TIMES.contains(&reddit.time.as_str()) {
+                    return invalid(format!("unknown reddit time '{}'", reddit.time));
+                }
+                if reddit.limit == 0 || reddit.limit > source::REDDIT_MAX_LIMIT {
+                    return invalid(format!(
+                        "reddit limit must be between 1 and {}",
+                        source::REDDIT_MAX_LIMIT
+                    ));
+                }
+                if let Some(min_resolution) = &reddit.min_resolution {
+                    if parse_size(min_resolution).is_none() {
+                        return invalid(format!(
+                            "invalid reddit min_resolution '{}'",
+                            min_resolution
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            SourceConfig::Local(local) => {
+                if local.path.is_empty() {
+                    return invalid("local needs a path".to_owned());
+                }
+                source::glob_set(&local.include)?;
+                source::glob_set(&local.exclude)?;
+                Ok(())
+            }
+            SourceConfig::Json(json) => {
+                if json.url.is_empty() {
+                    return invalid("json needs a url".to_owned());
+                }
+                if json.limit == 0 {
+                    return invalid("json limit must be at least 1".to_owned());
+                }
+                for path in [&json.items, &json.image]
+                    .into_iter()
+                    .chain(&json.total)
+                    .chain(&json.id)
+                    .chain(&json.title)
+                    .chain(&json.author)
+                    .chain(&json.permalink)
+                {
+                    source::check_path(path)?;
+                }
+                Ok(())
+            }
+            SourceConfig::Fake {
+                fail_every: Some(0),
+                ..
+            } => invalid("fake fail_every must be at least 1".to_owned()),
+            SourceConfig::Feed(feed) => {
+                if feed.url.is_empty() {
+                    return invalid("feed needs a url".to_owned());
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        SourceConfig::SimpleDesktops {
+            folder: default_simpledesktops_folder(),
+            url: default_simpledesktops_url(),
+        }
+    }
+}
+
+// Photos are picked at random by Unsplash, so the seed doesn't apply and
+// repeats are only avoided while matching photos are left undownloaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnsplashConfig {
+    pub folder: String,
+    pub url: String,
+    pub access_key: String,
+    // search terms, photos come from the whole site if unset
+    pub query: Option<String>,
+    // collection ids, not together with a query
+    pub collections: Vec<String>,
+    // landscape, portrait or squarish
+    pub orientation: Option<String>,
+    // only editorially featured photos, honored when picking at random
+    pub featured: bool,
+}
+
+impl Default for UnsplashConfig {
+    fn default() -> Self {
+        UnsplashConfig {
+            folder: "Unsplash".to_owned(),
+            url: source::URL_UNSPLASH.to_owned(),
+            access_key: String::new(),
+            query: None,
+            collections: Vec::new(),
+            orientation: Some("landscape".to_owned()),
+            featured: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BingConfig {
+    pub folder: String,
+    pub url: String,
+    // e.g. en-US, de-DE, ja-JP
+    pub market: String,
+    // UHD or a size like 1920x1080
+    pub resolution: String,
+    // how many of the latest images of the day to rotate through
+    pub days: u32,
+    // download every one of those days at once instead of on demand
+    pub backfill: bool,
+}
+
+impl Default for BingConfig {
+    fn default() -> Self {
+        BingConfig {
+            folder: "Bing".to_owned(),
+            url: source::URL_BING.to_owned(),
+            market: "en-US".to_owned(),
+            resolution: "UHD".to_owned(),
+            days: source::BING_MAX_DAYS,
+            backfill: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApodConfig {
+    pub folder: String,
+    pub url: String,
+    // DEMO_KEY works but is heavily rate limited
+    pub api_key: String,
+    // YYYY-MM-DD, the whole archive if unset
+    pub start_date: Option<String>,
+    // YYYY-MM-DD, today if unset
+    pub end_date: Option<String>,
+    // let the API pick from the whole archive instead of the date range
+    pub random: bool,
+}
+
+impl Default for ApodConfig {
+    fn default() -> Self {
+        ApodConfig {
+            folder: "APOD".to_owned(),
+            url: source::URL_APOD.to_owned(),
+            api_key: "DEMO_KEY".to_owned(),
+            start_date: None,
+            end_date: None,
+            random: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WallhavenConfig {
+    pub folder: String,
+    pub url: String,
+    // only needed for nsfw results
+    pub api_key: Option<String>,
+    // search terms and tags, like "+mountains -people"
+    pub query: Option<String>,
+    // general, anime and/or people
+    pub categories: Vec<String>,
+    // sfw, sketchy and/or nsfw
+    pub purity: Vec<String>,
+    // aspect ratios like 16x9 or 21x9
+    pub ratios: Vec<String>,
+    // minimum resolution like 1920x1080
+    pub atleast: Option<String>,
+    // date_added, relevance, random, views, favorites or toplist
+    pub sorting: String,
+    // period of the toplist: 1d, 3d, 1w, 1M, 3M, 6M or 1y
+    pub top_range: String,
+    // keeps the random order stable, generated at startup if unset
+    pub seed: Option<String>,
+}
+
+impl Default for WallhavenConfig {
+    fn default() -> Self {
+        WallhavenConfig {
+            folder: "Wallhaven".to_owned(),
+            url: source::URL_WALLHAVEN.to_owned(),
+            api_key: None,
+            query: None,
+            categories: vec!["general".to_owned()],
+            purity: vec!["sfw".to_owned()],
+            ratios: Vec::new(),
+            atleast: None,
+            sorting: "random".to_owned(),
+            top_range: "1M".to_owned(),
+            seed: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedditConfig {
+    pub folder: String,
+    pub url: String,
+    // without the r/ prefix
+    pub subreddits: Vec<String>,
+    // hot, new, top or rising
+    pub listing: String,
+    // window of the top listing: hour, day, week, month, year or all
+    pub time: String,
+    // how many posts to look at, at most 100
+    pub limit: u32,
+    // like 1920x1080, posts without a resolution in their title are skipped
+    pub min_resolution: Option<String>,
+}
+
+impl Default for RedditConfig {
+    fn default() -> Self {
+        RedditConfig {
+            folder: "Reddit".to_owned(),
+            url: source::URL_REDDIT.to_owned(),
+            subreddits: vec!["wallpapers".to_owned()],
+            listing: "top".to_owned(),
+            time: "week".to_owned(),
+            limit: source::REDDIT_MAX_LIMIT,
+            min_resolution: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalConfig {
+    // directory with the images
+    pub path: String,
+    pub recursive: bool,
+    // globs relative to `path`, like "**/*.jpg", everything if empty
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        LocalConfig {
+            path: String::new(),
+            recursive: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JsonConfig {
+    pub folder: String,
+    // {offset}, {limit} and {query} are replaced before each request
+    pub url: String,
+    pub query: Option<String>,
+    // wallpapers per request
+    pub limit: u32,
+    // JSONPath of the wallpapers in a response, like "$.objects[*]"
+    pub items: String,
+    // JSONPath within an item
+    pub image: String,
+    // JSONPath of the total in a response, only the first page is used without it
+    pub total: Option<String>,
+    // JSONPaths within an item
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub permalink: Option<String>,
+}
+
+impl Default for JsonConfig {
+    fn default() -> Self {
+        JsonConfig {
+            folder: "Json".to_owned(),
+            url: String::new(),
+            query: None,
+            limit: 20,
+            items: "$[*]".to_owned(),
+            image: "$.url".to_owned(),
+            total: None,
+            id: None,
+            title: None,
+            author: None,
+            permalink: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedConfig {
+    pub folder: String,
+    // RSS, Atom or Media RSS
+    pub url: String,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            folder: "Feed".to_owned(),
+            url: String::new(),
+        }
+    }
+}
+
+// "1920x1080" or "16x9"
+pub fn parse_size(size: &str) -> Option<(u32, u32)> {
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendConfig {
+    // detected from the running desktop if unset
+    pub name: Option<String>,
+    // KDE screen to change, every screen if unset
+    pub screen: Option<u32>,
+    // hyprpaper output to change, every output if empty
+    pub monitor: String,
+    pub hyprpaper_socket: Option<String>,
+    pub record_file: Option<String>,
+    pub swww: SwwwConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwwwConfig {
+    pub transition: String,
+    // seconds
+    pub duration: f32,
+}
+
+impl Default for SwwwConfig {
+    fn default() -> Self {
+        SwwwConfig {
+            transition: "fade".to_owned(),
+            duration: 2.0,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageConfig {
+    pub mode: ImageMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageMode {
+    // scale to cover the screen, cropping what doesn't fit
+    #[default]
+    Zoom,
+    Centered,
+    // scale to fit inside the screen
+    Scaled,
+    // a single image across every monitor
+    Spanned,
+}
+
+impl FromStr for ImageMode {
+    type Err = ApplicationError;
+
+    fn from_str(s: &str) -> ApplicationResult<Self> {
+        match s {
+            "zoom" => Ok(ImageMode::Zoom),
+            "centered" => Ok(ImageMode::Centered),
+            "scaled" => Ok(ImageMode::Scaled),
+            "spanned" => Ok(ImageMode::Spanned),
+            _ => Err(ApplicationError::ConfigError {
+                e: format!("Unknown image mode '{}'", s),
+            }),
+        }
+    }
+}
+
+fn parse_env<T: FromStr>(variable: &str, raw: &str) -> ApplicationResult<T> {
+    raw.parse()
+        .map_err(|_| ApplicationError::WrongEnvironmentVariable {
+            e: format!("Invalid value '{}' for {}", raw, variable),
+        })
+}
+
+// Reads an environment variable, swapped for a map in tests
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+fn env_override<T: FromStr>(env: Lookup, variable: &str, value: &mut T) -> ApplicationResult<()> {
+    if let Some(raw) = env(variable) {
+        *value = parse_env(variable, &raw)?;
+    }
+    Ok(())
+}
+
+fn env_override_option<T: FromStr>(
+    env: Lookup,
+    variable: &str,
+    value: &mut Option<T>,
+) -> ApplicationResult<()> {
+    if let Some(raw) = env(variable) {
+        *value = Some(parse_env(variable, &raw)?);
+    }
+    Ok(())
+}
+
+pub fn check_interval(minutes: u64) -> ApplicationResult<()> {
+    if !(1..=MAX_INTERVAL).contains(&minutes) {
+        return Err(ApplicationError::ConfigError {
+            e: format!(
+                "interval must be between 1 and {} minutes, not {}",
+                MAX_INTERVAL, minutes
+            ),
+        });
+    }
+    Ok(())
+}
+
+impl Config {
+    pub fn default_path() -> ApplicationResult<String> {
+        Ok(dirs::config_dir()? + "/" + CONFIG_FILENAME)
+    }
+
+    pub fn from_file(path: &str) -> ApplicationResult<Config> {
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| ApplicationError::ConfigError {
+            e: format!("{}: {}", path, e),
+        })
+    }
+
+    pub fn path(cli: &Cli) -> ApplicationResult<String> {
+        match &cli.config {
+            Some(path) => Ok(path.clone()),
+            None => Self::default_path(),
+        }
+    }
+
+    // The configuration file, overridden by the environment, overridden by
+    // the command line.
+    pub fn load(cli: &Cli) -> ApplicationResult<Config> {
+        Self::load_with_env(cli, &|variable| env::var(variable).ok())
+    }
+
+    fn load_with_env(cli: &Cli, env: Lookup) -> ApplicationResult<Config> {
+        let path = Self::path(cli)?;
+        // only the default file is optional
+        let mut config = if cli.config.is_some() || std::path::Path::new(&path).exists() {
+            Self::from_file(&path)?
+        } else {
+            log::debug!("No configuration file at '{}', using defaults", path);
+            Config::default()
+        };
+        config.apply_env(env)?;
+        config.apply_cli(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, env: Lookup) -> ApplicationResult<()> {
+        env_override_option(env, "SIMPLE_DESKTOP_DIRECTORY", &mut self.directory)?;
+        env_override(env, "SIMPLE_DESKTOP_TIMEOUT", &mut self.interval)?;
+        env_override_option(env, "SIMPLE_DESKTOP_BACKEND", &mut self.backend.name)?;
+        env_override(env, "SIMPLE_DESKTOP_PICTURE_OPTIONS", &mut self.image.mode)?;
+        env_override_option(env, "SIMPLE_DESKTOP_KDE_SCREEN", &mut self.backend.screen)?;
+        env_override(env, "SIMPLE_DESKTOP_MONITOR", &mut self.backend.monitor)?;
+        env_override_option(
+            env,
+            "SIMPLE_DESKTOP_HYPRPAPER_SOCKET",
+            &mut self.backend.hyprpaper_socket,
+        )?;
+        env_override_option(
+            env,
+            "SIMPLE_DESKTOP_RECORD_FILE",
+            &mut self.backend.record_file,
+        )?;
+        env_override(
+            env,
+            "SIMPLE_DESKTOP_SWWW_TRANSITION",
+            &mut self.backend.swww.transition,
+        )?;
+        env_override(
+            env,
+            "SIMPLE_DESKTOP_SWWW_DURATION",
+            &mut self.backend.swww.duration,
+        )
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(interval) = cli.interval {
+            self.interval = interval;
+        }
+        if let Some(directory) = &cli.directory {
+            self.directory = Some(directory.clone());
+        }
+        if let Some(backend) = &cli.backend {
+            self.backend.name = Some(backend.clone());
+        }
+    }
+
+    pub fn validate(&self) -> ApplicationResult<()> {
+        let invalid = |e: String| Err(ApplicationError::ConfigError { e });
+
+        check_interval(self.interval)?;
+        if self.sources.is_empty() {
+            return invalid("at least one source must be configured".to_owned());
+        }
+        for source in &self.sources {
+            if !source.weight.is_finite() || source.weight <= 0.0 {
+                return invalid(format!(
+                    "the weight of {} must be positive",
+                    source.source.folder()
+                ));
+            }
+            source.source.validate()?;
+        }
+        if let Some(name) = &self.backend.name {
+            if !backend::is_available(name) {
+                return invalid(format!("unknown backend '{}'", name));
+            }
+        }
+        if !SWWW_TRANSITIONS.contains(&self.backend.swww.transition.as_str()) {
+            return invalid(format!(
+                "unknown swww transition '{}'",
+                self.backend.swww.transition
+            ));
+        }
+        if !self.backend.swww.duration.is_finite() || self.backend.swww.duration < 0.0 {
+            return invalid(format!(
+                "invalid swww duration {}",
+                self.backend.swww.duration
+            ));
+        }
+        Ok(())
+    }
+
+    // The configuration without its secrets, to be shown
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        for weighted in &mut config.sources {
+            match &mut weighted.source {
+                SourceConfig::Unsplash(unsplash) => unsplash.access_key = REDACTED.to_owned(),
+                SourceConfig::Apod(apod) => apod.api_key = REDACTED.to_owned(),
+                SourceConfig::Wallhaven(wallhaven) => {
+                    if let Some(api_key) = &mut wallhaven.api_key {
+                        *api_key = REDACTED.to_owned();
+                    }
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+
+    pub fn socket_path(&self) -> ApplicationResult<String> {
+        match &self.socket {
+            Some(socket) => Ok(socket.clone()),
+            None => ipc::default_path(),
+        }
+    }
+
+    pub fn download_directory(&self) -> ApplicationResult<String> {
+        match &self.directory {
+            Some(directory) => Ok(directory.clone()),
+            None => dirs::pictures_dir(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use clap::Parser;
+    use std::collections::HashMap;
+
+    #[test]
+    fn interval_must_be_within_bounds() {
+        for interval in [1, 60, MAX_INTERVAL] {
+            let config = Config {
+                interval,
+                ..Config::default()
+            };
+            assert!(config.validate().is_ok(), "{} was refused", interval);
+        }
+        for interval in [0, MAX_INTERVAL + 1, 200_000_000_000_000_000, u64::MAX] {
+            let config = Config {
+                interval,
+                ..Config::default()
+            };
+            assert!(
+                matches!(config.validate(), Err(ApplicationError::ConfigError { .. })),
+                "{} was accepted",
+                interval
+            );
+        }
+    }
+
+    #[test]
+    fn unsplash_query_and_collections_exclude_each_other() {
+        let unsplash = |query: Option<&str>, collections: &[&str]| {
+            SourceConfig::Unsplash(UnsplashConfig {
+                access_key: "KEY".to_owned(),
+                query: query.map(str::to_owned),
+                collections: collections.iter().map(|&id| id.to_owned()).collect(),
+                ..UnsplashConfig::default()
+            })
+            .validate()
+        };
+        assert!(unsplash(Some("lake"), &[]).is_ok());
+        assert!(unsplash(None, &["317099"]).is_ok());
+        assert!(matches!(
+            unsplash(Some("lake"), &["317099"]),
+            Err(ApplicationError::ConfigError { .. })
+        ));
+    }
+
+    fn load(args: &[&str], env: &[(&str, &str)]) -> ApplicationResult<Config> {
+        let cli = Cli::parse_from(std::iter::once("rusty-wallpaper").chain(args.iter().copied()));
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(variable, value)| (variable.to_string(), value.to_string()))
+            .collect();
+        Config::load_with_env(&cli, &|variable| env.get(variable).cloned())
+    }
+
+    #[test]
+    fn the_environment_overrides_the_file_and_the_command_line_both() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "interval = 10\ndirectory = \"/from/file\"\n[backend]\nname = \"feh\"\nmonitor = \"DP-1\"\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = load(&["--config", path], &[]).unwrap();
+        assert_eq!(config.interval, 10);
+        assert_eq!(config.backend.monitor, "DP-1");
+
+        let env = [
+            ("SIMPLE_DESKTOP_TIMEOUT", "20"),
+            ("SIMPLE_DESKTOP_DIRECTORY", "/from/env"),
+            ("SIMPLE_DESKTOP_MONITOR", "HDMI-1"),
+        ];
+        let config = load(&["--config", path], &env).unwrap();
+        assert_eq!(config.interval, 20);
+        assert_eq!(config.directory.as_deref(), Some("/from/env"));
+        assert_eq!(config.backend.monitor, "HDMI-1");
+        assert_eq!(config.backend.name.as_deref(), Some("feh"));
+
+        let config = load(&["--config", path, "--interval", "30"], &env).unwrap();
+        assert_eq!(config.interval, 30);
+        assert_eq!(config.directory.as_deref(), Some("/from/env"));
+        assert_eq!(config.backend.monitor, "HDMI-1");
+    }
+
+    #[test]
+    fn only_the_default_file_may_be_missing() {
+        testing::isolate();
+        assert_eq!(load(&[], &[]).unwrap(), Config::default());
+
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.toml");
+        assert!(matches!(
+            load(&["--config", missing.to_str().unwrap()], &[]),
+            Err(ApplicationError::IoError { .. })
+        ));
+    }
+
+    #[test]
+    fn a_malformed_environment_variable_is_reported() {
+        match load(&[], &[("SIMPLE_DESKTOP_TIMEOUT", "soon")]) {
+            Err(ApplicationError::WrongEnvironmentVariable { e }) => {
+                assert!(e.contains("SIMPLE_DESKTOP_TIMEOUT"), "{}", e);
+                assert!(e.contains("soon"), "{}", e);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            load(&[], &[("SIMPLE_DESKTOP_PICTURE_OPTIONS", "tiled")]),
+            Err(ApplicationError::WrongEnvironmentVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let config = Config {
+            sources: vec![
+                WeightedSource {
+                    weight: 1.0,
+                    source: SourceConfig::Unsplash(UnsplashConfig {
+                        access_key: "UNSPLASH-SECRET".to_owned(),
+                        ..UnsplashConfig::default()
+                    }),
+                },
+                WeightedSource {
+                    weight: 1.0,
+                    source: SourceConfig::Apod(ApodConfig {
+                        api_key: "APOD-SECRET".to_owned(),
+                        ..ApodConfig::default()
+                    }),
+                },
+                WeightedSource {
+                    weight: 1.0,
+                    source: SourceConfig::Wallhaven(WallhavenConfig {
+                        api_key: Some("WALLHAVEN-SECRET".to_owned()),
+                        ..WallhavenConfig::default()
+                    }),
+                },
+            ],
+            ..Config::default()
+        };
+        let shown = toml::to_string_pretty(&config.redacted()).unwrap();
+        assert!(!shown.contains("SECRET"), "{}", shown);
+        assert_eq!(shown.matches(REDACTED).count(), 3, "{}", shown);
+        // the configuration itself is left alone
+        assert!(toml::to_string_pretty(&config)
+            .unwrap()
+            .contains("UNSPLASH-SECRET"));
+    }
+}
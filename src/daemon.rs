@@ -0,0 +1,603 @@
+use crate::backend::{self, WallpaperSetter};
+use crate::cli::Cli;
+use crate::commands;
+use crate::config::{self, Config, SourceConfig};
+use crate::history::History;
+use crate::ipc::{self, Request, Response, Status};
+use crate::reload;
+use crate::source::{self, Wallpaper, WallpaperSource};
+use crate::state;
+use crate::{ApplicationError, ApplicationResult};
+use rand::Rng;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+
+// How many wallpapers `previous` can go back to
+const HISTORY_LENGTH: usize = 50;
+// Banned wallpapers are skipped, but the source may have nothing else left
+const MAX_BANNED_SKIPS: usize = 10;
+// First delay before trying again after a failed change, doubled every time
+const BACKOFF_BASE: Duration = Duration::from_secs(30);
+
+// When a delay from now ends. Intervals are validated so this can't overflow,
+// unless the clock itself is about to.
+fn deadline(delay: Duration) -> ApplicationResult<Instant> {
+    Instant::now()
+        .checked_add(delay)
+        .ok_or_else(|| ApplicationError::ConfigError {
+            e: format!("{} seconds from now is too far away", delay.as_secs()),
+        })
+}
+
+struct Daemon {
+    config: Config,
+    download_directory: String,
+    setter: Box<dyn WallpaperSetter>,
+    source: Box<dyn WallpaperSource>,
+    history: Vec<Wallpaper>,
+    next_change: Instant,
+    // time left before the next change while paused
+    paused: Option<Duration>,
+    // failed scheduled changes in a row
+    failures: u32,
+    last_applied: Instant,
+}
+
+impl Daemon {
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval.saturating_mul(60))
+    }
+
+    // keeps the time already spent on the current wallpaper
+    fn set_interval(&mut self, minutes: u64) -> ApplicationResult<()> {
+        config::check_interval(minutes)?;
+        let elapsed = self
+            .interval()
+            .saturating_sub(self.next_change.saturating_duration_since(Instant::now()));
+        let interval = Duration::from_secs(minutes.saturating_mul(60));
+        self.next_change = deadline(interval.saturating_sub(elapsed))?;
+        self.config.interval = minutes;
+        Ok(())
+    }
+
+    fn apply(&mut self, wallpaper: Wallpaper) -> ApplicationResult<()> {
+        commands::apply(self.setter.as_mut(), &wallpaper)?;
+        self.history.push(wallpaper);
+        if self.history.len() > HISTORY_LENGTH {
+            self.history.remove(0);
+        }
+        self.next_change = deadline(self.interval())?;
+        self.last_applied = Instant::now();
+        Ok(())
+    }
+
+    // A wallpaper downloaded earlier, to keep rotating while offline
+    fn fallback(&mut self) -> ApplicationResult<()> {
+        let banned = state::load_list(state::BANNED_FILENAME)?;
+        let current = self.history.last().map(|wallpaper| wallpaper.path.clone());
+        let candidates = commands::cached_wallpapers(&self.config)?
+            .into_iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .filter(|path| Some(path) != current.as_ref())
+            .filter(|path| !banned.iter().any(|ban| &ban.path == path))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(ApplicationError::IoError {
+                e: "There is no cached wallpaper to fall back to".to_owned(),
+            });
+        }
+        let path = candidates[rand::thread_rng().gen_range(0, candidates.len())].clone();
+        // the sidecar has the metadata when the source wrote one
+        let wallpaper = fs::read_to_string(path.clone() + ".json")
+            .ok()
+            .and_then(|text| serde_json::from_str::<Wallpaper>(&text).ok())
+            .map(|wallpaper| Wallpaper {
+                path: path.clone(),
+                ..wallpaper
+            })
+            .unwrap_or(Wallpaper {
+                path,
+                ..Wallpaper::default()
+            });
+        log::info!("Falling back to the cached wallpaper '{}'", wallpaper.path);
+        self.apply(wallpaper)
+    }
+
+    fn backoff(&self) -> Duration {
+        let delay = BACKOFF_BASE
+            .saturating_mul(1 << self.failures.saturating_sub(1).min(16))
+            .min(self.interval());
+        // so that retries don't all land at the same time
+        delay.mul_f64(rand::thread_rng().gen_range(0.5, 1.0))
+    }
+
+    // Only errors that won't go away by themselves stop the daemon, the
+    // others are retried with a backoff while showing cached wallpapers.
+    async fn scheduled_change(&mut self) -> ApplicationResult<()> {
+        let error = match self.change().await {
+            Ok(()) => {
+                self.failures = 0;
+                return Ok(());
+            }
+            Err(e) if !e.is_retryable() => return Err(e),
+            Err(e) => e,
+        };
+        self.failures += 1;
+        log::warn!(
+            "Cannot change the wallpaper ({} failures in a row): {}",
+            self.failures,
+            error
+        );
+        if self.last_applied.elapsed() >= self.interval() {
+            if let Err(e) = self.fallback() {
+                log::warn!("{}", e);
+            }
+        }
+        self.next_change = deadline(self.backoff())?;
+        log::debug!(
+            "Trying again in {} seconds",
+            self.next_change
+                .saturating_duration_since(Instant::now())
+                .as_secs()
+        );
+        Ok(())
+    }
+
+    async fn change(&mut self) -> ApplicationResult<()> {
+        let banned = state::load_list(state::BANNED_FILENAME)?;
+        let mut wallpaper = None;
+        for _ in 0..MAX_BANNED_SKIPS {
+            let candidate = self.source.fetch_random(&self.download_directory).await?;
+            if banned.iter().any(|ban| ban.key() == candidate.key()) {
+                log::trace!("Skipping banned wallpaper '{}'", candidate.path);
+            } else {
+                wallpaper = Some(candidate);
+                break;
+            }
+        }
+        match wallpaper {
+            Some(wallpaper) => self.apply(wallpaper),
+            None => Err(ApplicationError::ApiError {
+                e: "Could only find banned wallpapers".to_owned(),
+            }),
+        }
+    }
+
+    fn previous(&mut self) -> ApplicationResult<()> {
+        if self.history.len() < 2 {
+            return Err(ApplicationError::ApiError {
+                e: "There is no previous wallpaper".to_owned(),
+            });
+        }
+        self.history.pop();
+        let previous = self.history.pop().unwrap_or_default();
+        self.apply(previous)
+    }
+
+    fn current(&self) -> ApplicationResult<Wallpaper> {
+        self.history
+            .last()
+            .cloned()
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: "No wallpaper has been applied yet".to_owned(),
+            })
+    }
+
+    fn status(&self) -> Status {
+        Status {
+            paused: self.paused.is_some(),
+            interval: self.config.interval,
+            next_change_in: match self.paused {
+                Some(_) => None,
+                None => Some(
+                    self.next_change
+                        .saturating_duration_since(Instant::now())
+                        .as_secs(),
+                ),
+            },
+            current: self.history.last().cloned(),
+        }
+    }
+
+    async fn handle(&mut self, request: Request) -> ApplicationResult<()> {
+        log::trace!("Received {:?}", request);
+        match request {
+            Request::Next => self.change().await,
+            Request::Previous => self.previous(),
+            Request::Pause => {
+                if self.paused.is_none() {
+                    self.paused = Some(self.next_change.saturating_duration_since(Instant::now()));
+                }
+                Ok(())
+            }
+            Request::Resume => {
+                if let Some(remaining) = self.paused {
+                    self.next_change = deadline(remaining)?;
+                    self.paused = None;
+                }
+                Ok(())
+            }
+            Request::Status => Ok(()),
+            Request::Favorite => {
+                state::add_to_list(state::FAVORITES_FILENAME, &self.current()?)?;
+                Ok(())
+            }
+            Request::Ban => {
+                state::add_to_list(state::BANNED_FILENAME, &self.current()?)?;
+                self.change().await
+            }
+            Request::SetInterval { minutes } => self.set_interval(minutes),
+        }
+    }
+
+    async fn reload(&mut self, new_config: Config) {
+        // build everything first so a failure leaves the loop untouched
+        let new_setter =
+            if new_config.backend != self.config.backend || new_config.image != self.config.image {
+                backend::select(&new_config).map(Some)
+            } else {
+                Ok(None)
+            };
+        let new_source =
+            if new_config.sources != self.config.sources || new_config.seed != self.config.seed {
+                Ok(Some(
+                    source::from_sources(&new_config.sources, new_config.seed).await,
+                ))
+            } else {
+                Ok(None)
+            };
+        match (new_setter, new_source, new_config.download_directory()) {
+            (Ok(new_setter), Ok(new_source), Ok(directory)) => {
+                if let Err(e) = self.set_interval(new_config.interval) {
+                    log::error!("Keeping the previous configuration: {}", e);
+                    return;
+                }
+                if let Some(new_setter) = new_setter {
+                    self.setter = new_setter;
+                }
+                if let Some(new_source) = new_source {
+                    self.source = new_source;
+                }
+                self.download_directory = directory;
+                self.config = new_config;
+                log::trace!(
+                    "SimpleWallpaper: interval is now {} minutes",
+                    self.config.interval
+                );
+            }
+            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                log::error!("Keeping the previous configuration: {}", e);
+            }
+        }
+    }
+}
+
+// What `previous` can go back to after a restart, the oldest first
+fn recent_history() -> Vec<Wallpaper> {
+    match History::open().and_then(|history| history.recent(HISTORY_LENGTH as u32)) {
+        Ok(entries) => entries
+            .into_iter()
+            .rev()
+            .map(|entry| entry.wallpaper)
+            .collect(),
+        Err(e) => {
+            log::warn!("Cannot read the history: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub async fn run(cli: Cli, config: Config) -> ApplicationResult<()> {
+    let mut calls = ipc::serve(&config.socket_path()?)?;
+    let download_directory = config.download_directory()?;
+    let mut folders = vec![source::partial_downloads_dir()?];
+    for source in &config.sources {
+        if let SourceConfig::Local(_) = source.source {
+            continue;
+        }
+        let folder = Path::new(&download_directory).join(source.source.folder());
+        folders.push(folder.to_string_lossy().into_owned());
+    }
+    for folder in folders {
+        if let Err(e) = source::remove_partial_downloads(&folder) {
+            log::warn!("Cannot clean up '{}': {}", folder, e);
+        }
+    }
+    for source in &config.sources {
+        if let SourceConfig::SimpleDesktops { folder, .. } = &source.source {
+            let folder = Path::new(&download_directory).join(folder);
+            let folder = folder.to_string_lossy();
+            if let Err(e) = source::migrate_legacy_names(&folder) {
+                log::warn!("Cannot rename the wallpapers in '{}': {}", folder, e);
+            }
+        }
+    }
+    let mut daemon = Daemon {
+        download_directory,
+        setter: backend::select(&config)?,
+        source: source::from_sources(&config.sources, config.seed).await,
+        history: recent_history(),
+        next_change: deadline(Duration::from_secs(config.interval.saturating_mul(60)))?,
+        paused: None,
+        failures: 0,
+        last_applied: Instant::now(),
+        config: config.clone(),
+    };
+    let mut config_rx = reload::watch(cli, config);
+    let shutdown = shutdown();
+    tokio::pin!(shutdown);
+
+    let result = loop {
+        tokio::select! {
+            _ = time::sleep_until(daemon.next_change), if daemon.paused.is_none() => {
+                if let Err(e) = daemon.scheduled_change().await {
+                    break Err(e);
+                }
+            }
+            _ = &mut shutdown => {
+                log::info!("Stopping");
+                break Ok(());
+            }
+            Ok(()) = config_rx.changed() => {
+                let new_config = (**config_rx.borrow_and_update()).clone();
+                daemon.reload(new_config).await;
+            }
+            Some((request, reply)) = calls.recv() => {
+                let response = match daemon.handle(request).await {
+                    Ok(()) => Response::status(daemon.status()),
+                    Err(e) => Response::error(&e),
+                };
+                let _ = reply.send(response);
+            }
+        }
+    };
+    if let Err(e) = History::open().and_then(|history| history.close()) {
+        log::warn!("Cannot end the wallpaper in the history: {}", e);
+    }
+    result
+}
+
+// Ctrl+C, or SIGTERM from a service manager
+async fn shutdown() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = terminate.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::RecordingSetter;
+    use crate::config::WeightedSource;
+    use crate::source::FakeSource;
+    use crate::testing;
+    use async_trait::async_trait;
+
+    // Always fails with the same error
+    struct BrokenSource(ApplicationError);
+
+    #[async_trait]
+    impl WallpaperSource for BrokenSource {
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+
+        async fn count(&self) -> ApplicationResult<u32> {
+            Err(self.0.clone())
+        }
+
+        async fn metadata(&self, _index: u32) -> ApplicationResult<Wallpaper> {
+            Err(self.0.clone())
+        }
+
+        async fn fetch(&self, _index: u32, _dir: &str) -> ApplicationResult<Wallpaper> {
+            Err(self.0.clone())
+        }
+    }
+
+    // Downloads go to `directory`, where the fake source keeps its images
+    fn daemon<S: WallpaperSource + 'static>(directory: &str, source: S) -> Daemon {
+        let config = Config {
+            directory: Some(directory.to_owned()),
+            sources: vec![WeightedSource {
+                weight: 1.0,
+                source: SourceConfig::Fake {
+                    folder: "Fake".to_owned(),
+                    count: 3,
+                    fail_every: None,
+                },
+            }],
+            ..Config::default()
+        };
+        Daemon {
+            download_directory: directory.to_owned(),
+            setter: Box::new(RecordingSetter::new(None)),
+            source: Box::new(source),
+            history: Vec::new(),
+            next_change: Instant::now() + Duration::from_secs(config.interval * 60),
+            paused: None,
+            failures: 0,
+            last_applied: Instant::now(),
+            config,
+        }
+    }
+
+    fn remaining(daemon: &Daemon) -> u64 {
+        daemon
+            .next_change
+            .saturating_duration_since(Instant::now())
+            .as_secs()
+    }
+
+    #[tokio::test]
+    async fn set_interval_keeps_the_elapsed_time() {
+        let mut daemon = daemon("", FakeSource::new("Fake", 3, None));
+        daemon.next_change = Instant::now() + Duration::from_secs(50 * 60);
+
+        daemon
+            .handle(Request::SetInterval { minutes: 30 })
+            .await
+            .unwrap();
+
+        assert_eq!(daemon.config.interval, 30);
+        assert!((19 * 60..=20 * 60).contains(&remaining(&daemon)));
+    }
+
+    #[tokio::test]
+    async fn set_interval_refuses_what_would_overflow() {
+        let mut daemon = daemon("", FakeSource::new("Fake", 3, None));
+
+        for minutes in [
+            0,
+            config::MAX_INTERVAL + 1,
+            999_999_999_999_999_999,
+            u64::MAX,
+        ] {
+            let result = daemon.handle(Request::SetInterval { minutes }).await;
+            assert!(
+                matches!(result, Err(ApplicationError::ConfigError { .. })),
+                "{} was accepted",
+                minutes
+            );
+        }
+
+        assert_eq!(daemon.config.interval, 60);
+        assert!(remaining(&daemon) > 59 * 60);
+        daemon
+            .handle(Request::SetInterval {
+                minutes: config::MAX_INTERVAL,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_interval() {
+        let mut daemon = daemon("", FakeSource::new("Fake", 3, None));
+        let base = BACKOFF_BASE.as_secs_f64();
+        for (failures, full) in [
+            (1, base),
+            (2, 2.0 * base),
+            (3, 4.0 * base),
+            (5, 16.0 * base),
+        ] {
+            daemon.failures = failures;
+            for _ in 0..20 {
+                let delay = daemon.backoff().as_secs_f64();
+                assert!(
+                    (full / 2.0..=full).contains(&delay),
+                    "{} after {} failures",
+                    delay,
+                    failures
+                );
+            }
+        }
+        // an hour between changes, so never more than an hour between retries
+        for failures in [8, 30, u32::MAX] {
+            daemon.failures = failures;
+            let delay = daemon.backoff();
+            assert!(delay <= daemon.interval());
+            assert!(delay >= daemon.interval() / 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn failures_are_retried_later_and_later() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let mut daemon = daemon(
+            &dir.path().to_string_lossy(),
+            FakeSource::new("Fake", 3, Some(1)),
+        );
+
+        let mut delays = Vec::new();
+        for _ in 0..4 {
+            daemon.scheduled_change().await.unwrap();
+            delays.push(remaining(&daemon));
+        }
+
+        assert_eq!(daemon.failures, 4);
+        let base = BACKOFF_BASE.as_secs();
+        for (failures, delay) in delays.iter().enumerate() {
+            let full = base << failures;
+            assert!(
+                (full / 2 - 1..=full).contains(delay),
+                "{} seconds after {} failures",
+                delay,
+                failures + 1
+            );
+        }
+        // nothing was applied, the current wallpaper is still recent
+        assert!(daemon.history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_success_resets_the_failures() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let mut daemon = daemon(
+            &dir.path().to_string_lossy(),
+            FakeSource::new("Fake", 3, Some(2)),
+        );
+
+        daemon.scheduled_change().await.unwrap();
+        daemon.scheduled_change().await.unwrap();
+        assert_eq!(daemon.failures, 1);
+        daemon.scheduled_change().await.unwrap();
+
+        assert_eq!(daemon.failures, 0);
+        assert_eq!(daemon.history.len(), 2);
+        assert!(remaining(&daemon) > 59 * 60);
+    }
+
+    #[tokio::test]
+    async fn cached_wallpapers_are_shown_when_changes_are_overdue() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_string_lossy().into_owned();
+        let cached = FakeSource::new("Fake", 3, None)
+            .fetch(1, &directory)
+            .await
+            .unwrap();
+        let mut daemon = daemon(&directory, FakeSource::new("Fake", 3, Some(1)));
+        daemon.last_applied = Instant::now() - daemon.interval();
+
+        daemon.scheduled_change().await.unwrap();
+
+        assert_eq!(daemon.failures, 1);
+        assert_eq!(daemon.current().unwrap().path, cached.path);
+        // the failed change is still retried soon
+        assert!(remaining(&daemon) <= BACKOFF_BASE.as_secs());
+    }
+
+    #[tokio::test]
+    async fn fatal_errors_stop_the_daemon() {
+        testing::isolate();
+        for error in [
+            ApplicationError::ConfigError {
+                e: "no source".to_owned(),
+            },
+            ApplicationError::HttpError {
+                status: 401,
+                e: "invalid key".to_owned(),
+            },
+        ] {
+            let mut daemon = daemon("", BrokenSource(error.clone()));
+            match daemon.scheduled_change().await {
+                Err(e) => assert_eq!(e.to_string(), error.to_string()),
+                Ok(()) => panic!("{} was retried", error),
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+#[cfg(not(windows))]
+use crate::ApplicationError;
+use crate::ApplicationResult;
+#[cfg(not(windows))]
+use std::{env, fs};
+
+const APPLICATION_NAME: &str = "rusty-wallpaper";
+
+#[cfg(windows)]
+mod special {
+    use crate::{ApplicationError, ApplicationResult};
+    use ::windows::Win32::Foundation::BOOL;
+    use ::windows::Win32::Foundation::HWND;
+    use ::windows::Win32::Foundation::MAX_PATH;
+    use ::windows::Win32::UI::Shell::SHGetSpecialFolderPathW;
+
+    pub use ::windows::Win32::UI::Shell::{CSIDL_APPDATA, CSIDL_LOCAL_APPDATA, CSIDL_MYPICTURES};
+
+    pub fn get_special_directory(csidl: i32) -> ApplicationResult<String> {
+        let mut buffer = [0; MAX_PATH as usize];
+        let result = unsafe { SHGetSpecialFolderPathW(HWND::default(), &mut buffer, csidl, false) };
+
+        if result != BOOL(0) {
+            Ok(String::from_utf16_lossy(&buffer)
+                .trim_matches(char::from(0))
+                .to_string())
+        } else {
+            Err(ApplicationError::WindowsOSError {
+                e: format!(
+                    "SHGetSpecialFolderPathW failed: {}",
+                    std::io::Error::last_os_error()
+                ),
+            })
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn pictures_dir() -> ApplicationResult<String> {
+    special::get_special_directory(special::CSIDL_MYPICTURES as _)
+}
+
+#[cfg(windows)]
+pub fn cache_dir() -> ApplicationResult<String> {
+    Ok(
+        special::get_special_directory(special::CSIDL_LOCAL_APPDATA as _)?
+            + "\\"
+            + APPLICATION_NAME
+            + "\\cache",
+    )
+}
+
+#[cfg(windows)]
+pub fn state_dir() -> ApplicationResult<String> {
+    Ok(
+        special::get_special_directory(special::CSIDL_LOCAL_APPDATA as _)?
+            + "\\"
+            + APPLICATION_NAME
+            + "\\state",
+    )
+}
+
+#[cfg(windows)]
+pub fn config_dir() -> ApplicationResult<String> {
+    Ok(special::get_special_directory(special::CSIDL_APPDATA as _)? + "\\" + APPLICATION_NAME)
+}
+
+#[cfg(not(windows))]
+fn home_dir() -> ApplicationResult<String> {
+    env::var("HOME").map_err(|_| ApplicationError::WrongEnvironmentVariable {
+        e: "HOME is not set".to_owned(),
+    })
+}
+
+// The XDG base directory spec says relative paths must be ignored
+#[cfg(not(windows))]
+fn xdg_base_dir(variable: &str, default: &str) -> ApplicationResult<String> {
+    match env::var(variable) {
+        Ok(dir) if dir.starts_with('/') => Ok(dir),
+        _ => Ok(home_dir()? + "/" + default),
+    }
+}
+
+// Reads a single entry like `XDG_PICTURES_DIR="$HOME/Pictures"` from
+// `user-dirs.dirs`, as written by xdg-user-dirs-update. An empty variable
+// counts as unset.
+#[cfg(not(windows))]
+fn xdg_user_dir(name: &str) -> ApplicationResult<Option<String>> {
+    match env::var(name) {
+        Ok(dir) if !dir.is_empty() => return Ok(Some(dir)),
+        _ => {}
+    }
+    let user_dirs = xdg_base_dir("XDG_CONFIG_HOME", ".config")? + "/user-dirs.dirs";
+    let content = match fs::read_to_string(&user_dirs) {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+    Ok(parse_user_dir(&content, name, &home_dir()?))
+}
+
+#[cfg(not(windows))]
+fn parse_user_dir(content: &str, name: &str, home: &str) -> Option<String> {
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+        let value = match line.split_once('=') {
+            Some((key, value)) if key.trim() == name => value.trim().trim_matches('"'),
+            _ => continue,
+        };
+        let dir = if let Some(relative) = value.strip_prefix("$HOME") {
+            home.to_owned() + relative
+        } else if value.starts_with('/') {
+            value.to_owned()
+        } else {
+            continue;
+        };
+        // xdg-user-dirs disables a directory by pointing it at $HOME
+        if dir.trim_end_matches('/') == home.trim_end_matches('/') {
+            return None;
+        }
+        return Some(dir);
+    }
+    None
+}
+
+#[cfg(not(windows))]
+pub fn pictures_dir() -> ApplicationResult<String> {
+    match xdg_user_dir("XDG_PICTURES_DIR")? {
+        Some(dir) => Ok(dir),
+        None => Ok(xdg_base_dir("XDG_DATA_HOME", ".local/share")? + "/" + APPLICATION_NAME),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn cache_dir() -> ApplicationResult<String> {
+    Ok(xdg_base_dir("XDG_CACHE_HOME", ".cache")? + "/" + APPLICATION_NAME)
+}
+
+#[cfg(not(windows))]
+pub fn state_dir() -> ApplicationResult<String> {
+    Ok(xdg_base_dir("XDG_STATE_HOME", ".local/state")? + "/" + APPLICATION_NAME)
+}
+
+#[cfg(not(windows))]
+pub fn config_dir() -> ApplicationResult<String> {
+    Ok(xdg_base_dir("XDG_CONFIG_HOME", ".config")? + "/" + APPLICATION_NAME)
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+
+    const USER_DIRS: &str = r#"
+# This file is written by xdg-user-dirs-update
+# XDG_PICTURES_DIR="/commented/out"
+XDG_DESKTOP_DIR="$HOME/Desktop"
+XDG_MUSIC_DIR=Music
+XDG_MUSIC_DIR="/srv/music"
+  XDG_PICTURES_DIR = "$HOME/Pictures/Wallpapers"
+XDG_VIDEOS_DIR="$HOME/"
+XDG_TEMPLATES_DIR="$HOME"
+"#;
+
+    fn dir(name: &str) -> Option<String> {
+        parse_user_dir(USER_DIRS, name, "/home/ann")
+    }
+
+    #[test]
+    fn home_is_expanded_and_comments_are_ignored() {
+        assert_eq!(
+            dir("XDG_PICTURES_DIR").as_deref(),
+            Some("/home/ann/Pictures/Wallpapers")
+        );
+        assert_eq!(dir("XDG_DESKTOP_DIR").as_deref(), Some("/home/ann/Desktop"));
+        assert_eq!(dir("XDG_DOWNLOAD_DIR"), None);
+    }
+
+    #[test]
+    fn relative_paths_are_skipped() {
+        assert_eq!(dir("XDG_MUSIC_DIR").as_deref(), Some("/srv/music"));
+    }
+
+    #[test]
+    fn a_directory_pointing_at_home_is_disabled() {
+        assert_eq!(dir("XDG_TEMPLATES_DIR"), None);
+        assert_eq!(dir("XDG_VIDEOS_DIR"), None);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::dirs;
+use crate::source::Wallpaper;
+use crate::state;
+use crate::ApplicationResult;
+use rusqlite::{params, Connection, Row};
+use std::fs;
+
+const HISTORY_FILENAME: &str = "history.sqlite";
+
+// Every wallpaper applied, kept in the state directory
+pub struct History {
+    connection: Connection,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub wallpaper: Wallpaper,
+    // seconds since the unix epoch
+    pub applied_at: u64,
+    // seconds it was shown, None while it is the current one
+    pub duration: Option<u64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SourceStats {
+    pub source: String,
+    pub shown: u64,
+    pub distinct: u64,
+    // seconds
+    pub duration: u64,
+}
+
+impl History {
+    pub fn open() -> ApplicationResult<History> {
+        fs::create_dir_all(dirs::state_dir()?)?;
+        History::with_connection(Connection::open(
+            dirs::state_dir()? + "/" + HISTORY_FILENAME,
+        )?)
+    }
+
+    fn with_connection(connection: Connection) -> ApplicationResult<History> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY,
+                source TEXT,
+                remote_id TEXT,
+                title TEXT,
+                creator TEXT,
+                permalink TEXT,
+                url TEXT,
+                attribution TEXT,
+                path TEXT NOT NULL,
+                applied_at INTEGER NOT NULL,
+                duration INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS history_applied_at ON history (applied_at);",
+        )?;
+        Ok(History { connection })
+    }
+
+    // Ends the current wallpaper and starts `wallpaper`
+    pub fn record(&self, wallpaper: &Wallpaper) -> ApplicationResult<()> {
+        self.record_at(wallpaper, state::now())
+    }
+
+    fn record_at(&self, wallpaper: &Wallpaper, now: u64) -> ApplicationResult<()> {
+        let transaction = self.connection.unchecked_transaction()?;
+        Self::end_current(&transaction, now)?;
+        transaction.execute(
+            "INSERT INTO history
+                (source, remote_id, title, creator, permalink, url, attribution, path, applied_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                wallpaper.source,
+                wallpaper.id,
+                wallpaper.title,
+                wallpaper.creator,
+                wallpaper.permalink,
+                wallpaper.url,
+                wallpaper.attribution,
+                wallpaper.path,
+                now as i64
+            ],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    // Ends the current wallpaper when the daemon stops, so that the time it
+    // isn't running doesn't count as display time
+    pub fn close(&self) -> ApplicationResult<()> {
+        Self::end_current(&self.connection, state::now())
+    }
+
+    fn end_current(connection: &Connection, now: u64) -> ApplicationResult<()> {
+        connection.execute(
+            "UPDATE history SET duration = MAX(?1 - applied_at, 0) WHERE duration IS NULL",
+            params![now as i64],
+        )?;
+        Ok(())
+    }
+
+    fn entry(row: &Row) -> rusqlite::Result<Entry> {
+        Ok(Entry {
+            wallpaper: Wallpaper {
+                source: row.get("source")?,
+                id: row.get("remote_id")?,
+                title: row.get("title")?,
+                creator: row.get("creator")?,
+                permalink: row.get("permalink")?,
+                url: row.get("url")?,
+                path: row.get("path")?,
+                attribution: row.get("attribution")?,
+            },
+            applied_at: row.get::<_, i64>("applied_at")? as u64,
+            duration: row
+                .get::<_, Option<i64>>("duration")?
+                .map(|duration| duration as u64),
+        })
+    }
+
+    // The last `limit` wallpapers, the most recent first
+    pub fn recent(&self, limit: u32) -> ApplicationResult<Vec<Entry>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT * FROM history ORDER BY applied_at DESC, id DESC LIMIT ?1")?;
+        let entries = statement
+            .query_map(params![limit], Self::entry)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    pub fn stats(&self) -> ApplicationResult<Vec<SourceStats>> {
+        let mut statement = self.connection.prepare(
+            "SELECT COALESCE(source, 'local') AS name,
+                COUNT(*),
+                COUNT(DISTINCT COALESCE(url, path)),
+                SUM(COALESCE(duration, MAX(?1 - applied_at, 0)))
+            FROM history GROUP BY name ORDER BY COUNT(*) DESC",
+        )?;
+        let stats = statement
+            .query_map(params![state::now() as i64], |row| {
+                Ok(SourceStats {
+                    source: row.get(0)?,
+                    shown: row.get::<_, i64>(1)? as u64,
+                    distinct: row.get::<_, i64>(2)? as u64,
+                    duration: row.get::<_, i64>(3)? as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(stats)
+    }
+
+    // The wallpapers shown the most, with how many times
+    pub fn most_shown(&self, limit: u32) -> ApplicationResult<Vec<(Entry, u64)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT *, COUNT(*) AS shown FROM history
+            GROUP BY COALESCE(url, path) ORDER BY shown DESC, MAX(applied_at) DESC LIMIT ?1",
+        )?;
+        let entries = statement
+            .query_map(params![limit], |row| {
+                Ok((Self::entry(row)?, row.get::<_, i64>("shown")? as u64))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> History {
+        History::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn wallpaper(source: Option<&str>, name: &str) -> Wallpaper {
+        Wallpaper {
+            path: format!("/pictures/{}.jpg", name),
+            source: source.map(str::to_owned),
+            url: source.map(|source| format!("https://{}/{}.jpg", source, name)),
+            title: Some(name.to_owned()),
+            ..Wallpaper::default()
+        }
+    }
+
+    #[test]
+    fn recording_ends_the_current_wallpaper() {
+        let history = history();
+        history
+            .record_at(&wallpaper(Some("bing"), "a"), 1000)
+            .unwrap();
+        history
+            .record_at(&wallpaper(Some("bing"), "b"), 1060)
+            .unwrap();
+        history.record_at(&wallpaper(None, "c"), 1300).unwrap();
+
+        let recent = history.recent(10).unwrap();
+        let names: Vec<_> = recent
+            .iter()
+            .map(|entry| entry.wallpaper.title.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["c", "b", "a"]);
+        assert_eq!(recent[0].wallpaper, wallpaper(None, "c"));
+        assert_eq!(recent[0].applied_at, 1300);
+        assert_eq!(recent[0].duration, None);
+        assert_eq!(recent[1].duration, Some(240));
+        assert_eq!(recent[2].duration, Some(60));
+        assert_eq!(history.recent(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn closing_stops_the_clock_until_the_next_wallpaper() {
+        let history = history();
+        history
+            .record_at(&wallpaper(Some("bing"), "a"), 1000)
+            .unwrap();
+        History::end_current(&history.connection, 1100).unwrap();
+        // the daemon was stopped in between
+        history
+            .record_at(&wallpaper(Some("bing"), "b"), 90_000)
+            .unwrap();
+        assert_eq!(history.recent(2).unwrap()[1].duration, Some(100));
+    }
+
+    #[test]
+    fn a_clock_going_backwards_gives_no_negative_duration() {
+        let history = history();
+        history
+            .record_at(&wallpaper(Some("bing"), "a"), 1000)
+            .unwrap();
+        history
+            .record_at(&wallpaper(Some("bing"), "b"), 900)
+            .unwrap();
+        // "b" looks older than "a"
+        assert_eq!(history.recent(2).unwrap()[0].duration, Some(0));
+    }
+
+    #[test]
+    fn stats_are_grouped_by_source() {
+        let history = history();
+        history
+            .record_at(&wallpaper(Some("bing"), "a"), 1000)
+            .unwrap();
+        history
+            .record_at(&wallpaper(Some("bing"), "a"), 1010)
+            .unwrap();
+        history
+            .record_at(&wallpaper(Some("bing"), "b"), 1030)
+            .unwrap();
+        history.record_at(&wallpaper(None, "c"), 1100).unwrap();
+        History::end_current(&history.connection, 1400).unwrap();
+
+        let stats = history.stats().unwrap();
+        assert_eq!(
+            stats,
+            [
+                SourceStats {
+                    source: "bing".to_owned(),
+                    shown: 3,
+                    distinct: 2,
+                    duration: 100,
+                },
+                SourceStats {
+                    source: "local".to_owned(),
+                    shown: 1,
+                    distinct: 1,
+                    duration: 300,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn most_shown_counts_each_wallpaper() {
+        let history = history();
+        for (name, at) in [("a", 1), ("b", 2), ("a", 3), ("c", 4), ("c", 5), ("a", 6)] {
+            history
+                .record_at(&wallpaper(Some("bing"), name), at)
+                .unwrap();
+        }
+        let shown: Vec<_> = history
+            .most_shown(2)
+            .unwrap()
+            .into_iter()
+            .map(|(entry, shown)| (entry.wallpaper.title.unwrap(), shown))
+            .collect();
+        assert_eq!(shown, [("a".to_owned(), 3), ("c".to_owned(), 2)]);
+    }
+}
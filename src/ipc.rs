@@ -0,0 +1,380 @@
+use crate::source::Wallpaper;
+use crate::{ApplicationError, ApplicationResult};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+
+// One JSON object per line in both directions, e.g.
+// {"command":"set-interval","minutes":30}
+#[derive(Subcommand, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Request {
+    /// Change the wallpaper now
+    Next,
+    /// Go back to the previous wallpaper
+    Previous,
+    /// Stop changing the wallpaper
+    Pause,
+    /// Start changing the wallpaper again
+    Resume,
+    /// Show what the daemon is doing
+    Status,
+    /// Remember the current wallpaper as a favorite
+    Favorite,
+    /// Never show the current wallpaper again and change it
+    Ban,
+    /// Change the interval until the configuration is reloaded
+    SetInterval { minutes: u64 },
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub paused: bool,
+    // minutes
+    pub interval: u64,
+    // seconds, none while paused
+    pub next_change_in: Option<u64>,
+    pub current: Option<Wallpaper>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+impl Response {
+    pub fn status(status: Status) -> Response {
+        Response {
+            ok: true,
+            error: None,
+            status: Some(status),
+        }
+    }
+
+    pub fn error(error: &ApplicationError) -> Response {
+        Response {
+            ok: false,
+            error: Some(error.to_string()),
+            status: None,
+        }
+    }
+}
+
+pub type Call = (Request, oneshot::Sender<Response>);
+
+#[cfg(unix)]
+pub fn default_path() -> ApplicationResult<String> {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(runtime_dir) if runtime_dir.starts_with('/') => {
+            Ok(runtime_dir + "/rusty-wallpaper.sock")
+        }
+        _ => Ok(crate::dirs::state_dir()? + "/rusty-wallpaper.sock"),
+    }
+}
+
+#[cfg(windows)]
+pub fn default_path() -> ApplicationResult<String> {
+    Ok(r"\\.\pipe\rusty-wallpaper".to_owned())
+}
+
+async fn handle_connection<S>(stream: S, calls: mpsc::Sender<Call>) -> ApplicationResult<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if calls.send((request, reply_tx)).await.is_err() {
+                    break;
+                }
+                reply_rx.await.unwrap_or_else(|_| {
+                    Response::error(&ApplicationError::ApiError {
+                        e: "The daemon is shutting down".to_owned(),
+                    })
+                })
+            }
+            Err(e) => Response::error(&e.into()),
+        };
+        let mut reply = serde_json::to_string(&response)?;
+        reply.push('\n');
+        writer.write_all(reply.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+// Listens on the control socket, every request is forwarded to the
+// returned receiver together with the channel its response goes to.
+#[cfg(unix)]
+pub fn serve(path: &str) -> ApplicationResult<mpsc::Receiver<Call>> {
+    use std::path::Path;
+    use tokio::net::UnixListener;
+
+    if Path::new(path).exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(ApplicationError::IoError {
+                e: format!("Another daemon is already listening on '{}'", path),
+            });
+        }
+        // left over by a daemon that didn't shut down cleanly
+        std::fs::remove_file(path)?;
+    }
+    if let Some(parent) = Path::new(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    log::trace!("Listening for commands on '{}'", path);
+
+    let (calls_tx, calls_rx) = mpsc::channel(8);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let calls = calls_tx.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream, calls).await {
+                            log::warn!("Control connection failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => log::warn!("Cannot accept a control connection: {}", e),
+            }
+        }
+    });
+    Ok(calls_rx)
+}
+
+#[cfg(windows)]
+pub fn serve(path: &str) -> ApplicationResult<mpsc::Receiver<Call>> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let path = path.to_owned();
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)?;
+    log::trace!("Listening for commands on '{}'", path);
+
+    let (calls_tx, calls_rx) = mpsc::channel(8);
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = server.connect().await {
+                log::warn!("Cannot accept a control connection: {}", e);
+                continue;
+            }
+            let connected = server;
+            server = match ServerOptions::new().create(&path) {
+                Ok(server) => server,
+                Err(e) => {
+                    log::error!("Cannot create the control pipe: {}", e);
+                    return;
+                }
+            };
+            let calls = calls_tx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(connected, calls).await {
+                    log::warn!("Control connection failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(calls_rx)
+}
+
+async fn exchange<S>(stream: S, request: &Request) -> ApplicationResult<Response>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    match BufReader::new(reader).lines().next_line().await? {
+        Some(reply) => Ok(serde_json::from_str(&reply)?),
+        None => Err(ApplicationError::IoError {
+            e: "The daemon closed the connection without answering".to_owned(),
+        }),
+    }
+}
+
+#[cfg(unix)]
+pub async fn send(path: &str, request: &Request) -> ApplicationResult<Response> {
+    let stream =
+        tokio::net::UnixStream::connect(path)
+            .await
+            .map_err(|e| ApplicationError::IoError {
+                e: format!("Cannot reach the daemon on '{}': {}", path, e),
+            })?;
+    exchange(stream, request).await
+}
+
+#[cfg(windows)]
+pub async fn send(path: &str, request: &Request) -> ApplicationResult<Response> {
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new()
+        .open(path)
+        .map_err(|e| ApplicationError::IoError {
+            e: format!("Cannot reach the daemon on '{}': {}", path, e),
+        })?;
+    exchange(stream, request).await
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixStream;
+
+    // A socket path in a directory removed with the returned guard
+    fn socket() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("daemon.sock")
+            .to_string_lossy()
+            .into_owned();
+        (dir, path)
+    }
+
+    // Serves `path` with a daemon answering calls with a status echoing the
+    // interval they gave, which returns the first `limit` requests
+    fn answer(path: &str, limit: usize) -> tokio::task::JoinHandle<Vec<Request>> {
+        let mut calls = serve(path).unwrap();
+        tokio::spawn(async move {
+            let mut requests = Vec::new();
+            while let Some((request, reply)) = calls.recv().await {
+                let interval = match request {
+                    Request::SetInterval { minutes } => minutes,
+                    _ => 0,
+                };
+                requests.push(request);
+                let _ = reply.send(Response::status(Status {
+                    interval,
+                    ..Status::default()
+                }));
+                if requests.len() == limit {
+                    break;
+                }
+            }
+            requests
+        })
+    }
+
+    async fn raw_exchange(path: &str, line: &str) -> Response {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        stream.write_all(line.as_bytes()).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    #[tokio::test]
+    async fn every_request_makes_the_round_trip() {
+        let (_dir, path) = socket();
+        let requests = [
+            Request::Next,
+            Request::Previous,
+            Request::Pause,
+            Request::Resume,
+            Request::Status,
+            Request::Favorite,
+            Request::Ban,
+            Request::SetInterval { minutes: 42 },
+        ];
+        let daemon = answer(&path, requests.len());
+
+        for request in &requests {
+            let response = send(&path, request).await.unwrap();
+            assert!(response.ok);
+            assert_eq!(response.error, None);
+            let expected = match request {
+                Request::SetInterval { minutes } => *minutes,
+                _ => 0,
+            };
+            assert_eq!(response.status.unwrap().interval, expected);
+        }
+
+        assert_eq!(daemon.await.unwrap(), requests);
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_get_an_error() {
+        let (_dir, path) = socket();
+        let _daemon = answer(&path, usize::MAX);
+
+        for line in [
+            "not json\n",
+            "{\"command\":\"explode\"}\n",
+            "{\"command\":\"set-interval\",\"minutes\":-1}\n",
+        ] {
+            let response = raw_exchange(&path, line).await;
+            assert!(!response.ok, "{} was accepted", line);
+            assert!(response.error.unwrap().contains("DeserializationError"));
+            assert_eq!(response.status, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn several_requests_share_a_connection() {
+        let (_dir, path) = socket();
+        let _daemon = answer(&path, usize::MAX);
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"{\"command\":\"pause\"}\n\n{\"command\":\"set-interval\",\"minutes\":5}\n")
+            .await
+            .unwrap();
+        stream.shutdown().await.unwrap();
+        let mut replies = String::new();
+        stream.read_to_string(&mut replies).await.unwrap();
+        let replies = replies
+            .lines()
+            .map(|line| serde_json::from_str::<Response>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[1].status.as_ref().unwrap().interval, 5);
+    }
+
+    #[tokio::test]
+    async fn a_stale_socket_is_replaced() {
+        let (_dir, path) = socket();
+        // bound and dropped, like after a crash
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(std::path::Path::new(&path).exists());
+
+        let _daemon = answer(&path, usize::MAX);
+
+        assert!(send(&path, &Request::Status).await.unwrap().ok);
+    }
+
+    #[tokio::test]
+    async fn a_live_socket_is_kept() {
+        let (_dir, path) = socket();
+        let _first = answer(&path, usize::MAX);
+
+        assert!(matches!(
+            serve(&path),
+            Err(ApplicationError::IoError { .. })
+        ));
+        assert!(send(&path, &Request::Status).await.unwrap().ok);
+    }
+
+    #[tokio::test]
+    async fn sending_without_a_daemon_fails() {
+        let (_dir, path) = socket();
+
+        match send(&path, &Request::Status).await {
+            Err(ApplicationError::IoError { e }) => assert!(e.contains("Cannot reach the daemon")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
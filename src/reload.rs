@@ -0,0 +1,135 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::{ApplicationError, ApplicationResult};
+use notify::{RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+// Reloads the configuration whenever its file changes or on SIGHUP. Only
+// valid configurations reach the receiver, a broken edit is logged and the
+// previous configuration stays in place.
+pub fn watch(cli: Cli, initial: Config) -> watch::Receiver<Arc<Config>> {
+    let (config_tx, config_rx) = watch::channel(Arc::new(initial));
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<()>();
+
+    let watcher = match Config::path(&cli) {
+        Ok(path) => watch_file(PathBuf::from(path), event_tx.clone())
+            .map_err(|e| log::warn!("Not watching the configuration file: {}", e))
+            .ok(),
+        Err(e) => {
+            log::warn!("Not watching the configuration file: {}", e);
+            None
+        }
+    };
+
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::hangup()) {
+            Ok(mut hangup) => {
+                while hangup.recv().await.is_some() {
+                    log::info!("Received SIGHUP, reloading the configuration");
+                    if event_tx.send(()).is_err() {
+                        break;
+                    }
+                }
+            }
+            Err(e) => log::warn!("Cannot listen for SIGHUP: {}", e),
+        }
+    });
+
+    tokio::spawn(async move {
+        // the watcher stops as soon as it is dropped
+        let _watcher = watcher;
+        while event_rx.recv().await.is_some() {
+            match Config::load(&cli) {
+                Ok(config) => {
+                    if **config_tx.borrow() != config {
+                        log::info!("Configuration reloaded");
+                        if config_tx.send(Arc::new(config)).is_err() {
+                            break;
+                        }
+                    }
+                }
+                Err(e) => log::error!("Ignoring invalid configuration: {}", e),
+            }
+        }
+    });
+
+    config_rx
+}
+
+// Editors usually replace the file instead of writing it in place, so the
+// parent directory is watched and events are filtered by path.
+fn watch_file(
+    path: PathBuf,
+    event_tx: mpsc::UnboundedSender<()>,
+) -> ApplicationResult<notify::RecommendedWatcher> {
+    let directory = match path.parent() {
+        Some(directory) if directory.as_os_str().is_empty() => PathBuf::from("."),
+        Some(directory) => directory.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    let filename = path.file_name().map(|name| name.to_os_string());
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let touches_config = event
+                .paths
+                .iter()
+                .any(|changed| changed.file_name().map(|name| name.to_os_string()) == filename);
+            if touches_config && (event.kind.is_create() || event.kind.is_modify()) {
+                let _ = event_tx.send(());
+            }
+        }
+    })
+    .map_err(notify_error)?;
+    watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .map_err(notify_error)?;
+    log::trace!("Watching '{}' for configuration changes", path.display());
+    Ok(watcher)
+}
+
+fn notify_error(err: notify::Error) -> ApplicationError {
+    ApplicationError::ConfigError { e: err.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::fs;
+    use std::time::Duration;
+    use tokio::time;
+
+    const SOURCES: &str = "[[sources]]\ntype = \"fake\"\n";
+
+    #[tokio::test]
+    async fn only_valid_edits_are_swapped_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, format!("interval = 10\n{}", SOURCES)).unwrap();
+        let cli = Cli::parse_from(["rusty-wallpaper", "--config", &path.to_string_lossy()]);
+        let initial = Config::load(&cli).unwrap();
+        let mut config_rx = watch(cli, initial);
+
+        // refused by validation, then not even TOML
+        fs::write(&path, format!("interval = 0\n{}", SOURCES)).unwrap();
+        fs::write(&path, "interval = [").unwrap();
+        assert!(
+            time::timeout(Duration::from_millis(500), config_rx.changed())
+                .await
+                .is_err()
+        );
+        assert_eq!(config_rx.borrow().interval, 10);
+
+        fs::write(&path, format!("interval = 5\n{}", SOURCES)).unwrap();
+        time::timeout(Duration::from_secs(10), config_rx.changed())
+            .await
+            .expect("the configuration wasn't reloaded")
+            .unwrap();
+        assert_eq!(config_rx.borrow_and_update().interval, 5);
+    }
+}
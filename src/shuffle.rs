@@ -0,0 +1,230 @@
+use crate::dirs;
+use crate::ApplicationResult;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+const SHUFFLE_FILENAME: &str = "shuffle.json";
+
+// Goes through the indices of a source in a random order, so every wallpaper
+// is shown once before any of them comes back. Only the seed and the position
+// are kept, the order of a round is computed again from them.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShuffleBag {
+    seed: u64,
+    // size of the source when the round started
+    count: u32,
+    // size the source grew to during the round, the new indices are shuffled
+    // after the others
+    #[serde(default)]
+    grown: u32,
+    round: u64,
+    position: u32,
+    // the wallpaper that ended the previous round, not to start this one with
+    avoid: Option<u32>,
+}
+
+impl ShuffleBag {
+    pub fn new(seed: u64) -> ShuffleBag {
+        ShuffleBag {
+            seed,
+            ..ShuffleBag::default()
+        }
+    }
+
+    fn len(&self) -> u32 {
+        self.count.max(self.grown)
+    }
+
+    fn order(&self) -> Vec<u32> {
+        let mut rng = StdRng::seed_from_u64(
+            self.seed
+                .wrapping_add(self.round.wrapping_mul(0x9E37_79B9_7F4A_7C15)),
+        );
+        let mut order = (0..self.count).collect::<Vec<_>>();
+        order.shuffle(&mut rng);
+        if order.len() > 1 && order.first() == self.avoid.as_ref() {
+            let end = order.len() - 1;
+            order.swap(0, end);
+        }
+        let mut added = (self.count..self.len()).collect::<Vec<_>>();
+        added.shuffle(&mut rng);
+        order.extend(added);
+        order
+    }
+
+    // New wallpapers join the current round, removed ones are skipped
+    pub fn next(&mut self, count: u32) -> Option<u32> {
+        if count == 0 {
+            return None;
+        }
+        if count > self.len() && self.position < self.len() {
+            self.grown = count;
+        }
+        let mut order = self.order();
+        loop {
+            if self.position >= self.len() {
+                self.avoid = order.last().copied();
+                self.round += 1;
+                self.count = count;
+                self.grown = count;
+                self.position = 0;
+                order = self.order();
+            }
+            let index = order[self.position as usize];
+            self.position += 1;
+            if index < count {
+                return Some(index);
+            }
+        }
+    }
+}
+
+fn shuffle_path() -> ApplicationResult<String> {
+    Ok(dirs::state_dir()? + "/" + SHUFFLE_FILENAME)
+}
+
+fn load_all() -> ApplicationResult<HashMap<String, ShuffleBag>> {
+    match fs::read_to_string(shuffle_path()?) {
+        // only the order is lost, better than never changing wallpapers again
+        Ok(content) => Ok(serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::warn!(
+                "Starting the shuffled order again, it cannot be read: {}",
+                e
+            );
+            HashMap::new()
+        })),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// The bag of the source identified by `key`. A configured seed replaces a bag
+// shuffled with another one, otherwise new bags get a random seed.
+pub fn load(key: &str, seed: Option<u64>) -> ApplicationResult<ShuffleBag> {
+    let bag = load_all()?.remove(key);
+    Ok(match (bag, seed) {
+        (Some(bag), Some(seed)) if bag.seed == seed => bag,
+        (Some(bag), None) => bag,
+        (_, Some(seed)) => ShuffleBag::new(seed),
+        (None, None) => ShuffleBag::new(rand::thread_rng().gen()),
+    })
+}
+
+pub fn save(key: &str, bag: &ShuffleBag) -> ApplicationResult<()> {
+    let mut bags = load_all()?;
+    bags.insert(key.to_owned(), bag.clone());
+    fs::create_dir_all(dirs::state_dir()?)?;
+    // written aside first so a crash can't leave half a file
+    let path = shuffle_path()?;
+    let temporary = path.clone() + ".tmp";
+    fs::write(&temporary, serde_json::to_string_pretty(&bags)?)?;
+    fs::rename(temporary, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::collections::HashSet;
+
+    fn round(bag: &mut ShuffleBag, count: u32) -> Vec<u32> {
+        (0..count).map(|_| bag.next(count).unwrap()).collect()
+    }
+
+    #[test]
+    fn a_round_shows_every_index_once() {
+        let mut bag = ShuffleBag::new(7);
+        for _ in 0..5 {
+            let mut shown = round(&mut bag, 10);
+            shown.sort();
+            assert_eq!(shown, (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn a_round_doesnt_start_with_the_end_of_the_previous_one() {
+        for seed in 0..50 {
+            let mut bag = ShuffleBag::new(seed);
+            let mut last = *round(&mut bag, 3).last().unwrap();
+            for _ in 0..20 {
+                let shown = round(&mut bag, 3);
+                assert_ne!(shown[0], last, "seed {}", seed);
+                last = *shown.last().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn a_seed_gives_the_same_order() {
+        let mut bag = ShuffleBag::new(42);
+        let mut same = ShuffleBag::new(42);
+        let mut other = ShuffleBag::new(43);
+        let shown = round(&mut bag, 50);
+        assert_eq!(round(&mut same, 50), shown);
+        assert_ne!(round(&mut other, 50), shown);
+        // and so does a bag read back mid round
+        let mut copy =
+            serde_json::from_str::<ShuffleBag>(&serde_json::to_string(&bag).unwrap()).unwrap();
+        assert_eq!(round(&mut copy, 50), round(&mut bag, 50));
+    }
+
+    #[test]
+    fn an_empty_source_has_nothing_to_show() {
+        assert_eq!(ShuffleBag::new(1).next(0), None);
+    }
+
+    #[test]
+    fn new_wallpapers_join_the_current_round() {
+        let mut bag = ShuffleBag::new(3);
+        let mut shown = (0..4).map(|_| bag.next(10).unwrap()).collect::<Vec<_>>();
+        shown.extend((0..11).map(|_| bag.next(15).unwrap()));
+        let distinct = shown.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(distinct, (0..15).collect());
+        // the next round has them all
+        let mut next = round(&mut bag, 15);
+        next.sort();
+        assert_eq!(next, (0..15).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn removed_wallpapers_are_skipped() {
+        let mut bag = ShuffleBag::new(5);
+        let mut shown = (0..4)
+            .map(|_| bag.next(10).unwrap())
+            .filter(|&index| index < 6)
+            .collect::<Vec<_>>();
+        shown.extend((shown.len()..6).map(|_| bag.next(6).unwrap()));
+        let distinct = shown.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(distinct, (0..6).collect());
+    }
+
+    #[test]
+    fn a_large_source_can_shrink_at_once() {
+        let mut bag = ShuffleBag::new(11);
+        bag.next(50_000);
+        let start = std::time::Instant::now();
+        let mut shown = round(&mut bag, 10);
+        shown.sort();
+        assert_eq!(shown, (0..10).collect::<Vec<_>>());
+        // one shuffle per pick, not one per skipped index
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn a_corrupt_file_starts_over() {
+        testing::isolate();
+        fs::create_dir_all(dirs::state_dir().unwrap()).unwrap();
+        fs::write(shuffle_path().unwrap(), "{\"unfinished").unwrap();
+        assert_eq!(load("corrupt", Some(9)).unwrap(), ShuffleBag::new(9));
+
+        let mut bag = ShuffleBag::new(9);
+        bag.next(4);
+        save("corrupt", &bag).unwrap();
+        assert_eq!(load("corrupt", Some(9)).unwrap(), bag);
+    }
+}
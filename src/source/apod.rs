@@ -0,0 +1,418 @@
+use super::{download, safe_filename, source_directory, Wallpaper, WallpaperSource};
+use crate::config::ApodConfig;
+use crate::shuffle::ShuffleBag;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use chrono::{FixedOffset, NaiveDate, TimeDelta, Utc};
+use rand::Rng;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+pub const URL_APOD: &str = "https://api.nasa.gov";
+// The first Astronomy Picture of the Day
+pub const APOD_FIRST_DATE: &str = "1995-06-16";
+// How many video (or missing) days in a row are skipped before giving up
+const MAX_SKIPPED_DAYS: u32 = 14;
+// Random mode asks for a few pictures at once in case some are videos
+const RANDOM_BATCH: u32 = 5;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Picture {
+    date: String,
+    title: String,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    hdurl: Option<String>,
+    media_type: String,
+    #[serde(default)]
+    copyright: Option<String>,
+}
+
+// Both shapes show up depending on which layer rejected the request
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    message: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiErrorBody {
+    #[serde(default)]
+    error: Option<ErrorDetail>,
+    #[serde(default)]
+    msg: Option<String>,
+}
+
+fn epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap()
+}
+
+// Days since 1970-01-01
+pub fn parse_apod_date(date: &str) -> ApplicationResult<i64> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|date| (date - epoch()).num_days())
+        .map_err(|_| ApplicationError::ConfigError {
+            e: format!("Invalid date '{}', expected YYYY-MM-DD", date),
+        })
+}
+
+fn format_date(days: i64) -> String {
+    (epoch() + TimeDelta::days(days))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+// APOD days start at midnight US Eastern time. Standard time is used all year
+// long, it is never ahead of the day there.
+fn today() -> i64 {
+    let eastern = FixedOffset::west_opt(5 * 3600).unwrap();
+    (Utc::now().with_timezone(&eastern).date_naive() - epoch()).num_days()
+}
+
+pub struct ApodSource {
+    pub config: ApodConfig,
+}
+
+impl ApodSource {
+    pub fn new(config: &ApodConfig) -> ApodSource {
+        ApodSource {
+            config: config.clone(),
+        }
+    }
+
+    fn range(&self) -> ApplicationResult<(i64, i64)> {
+        let start = parse_apod_date(self.config.start_date.as_deref().unwrap_or(APOD_FIRST_DATE))?;
+        let end = match &self.config.end_date {
+            Some(end_date) => parse_apod_date(end_date)?,
+            None => today(),
+        };
+        Ok((start, end))
+    }
+
+    // None when there is no picture for the requested day
+    async fn get(&self, params: &[(&str, String)]) -> ApplicationResult<Option<String>> {
+        let url = format!("{}/planetary/apod", self.config.url.trim_end_matches('/'));
+        let response = reqwest::Client::new()
+            .get(url)
+            .query(&[("api_key", self.config.api_key.as_str())])
+            .query(params)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if status.is_success() {
+            return Ok(Some(text));
+        }
+        let message = match serde_json::from_str::<ApiErrorBody>(&text) {
+            Ok(ApiErrorBody {
+                error: Some(detail),
+                ..
+            }) => detail.message,
+            Ok(ApiErrorBody { msg: Some(msg), .. }) => msg,
+            _ => text,
+        };
+        // a day that has no picture yet, or a day after the last one
+        if status == StatusCode::NOT_FOUND
+            || status == StatusCode::BAD_REQUEST && message.starts_with("Date must be between")
+        {
+            return Ok(None);
+        }
+        // kept apart so that a rejected API key stops the daemon
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            return Err(ApplicationError::HttpError {
+                status: status.as_u16(),
+                e: message,
+            });
+        }
+        Err(ApplicationError::ApiError { e: message })
+    }
+
+    // The picture `index` days before the end of the range, or the closest
+    // earlier day that isn't a video.
+    async fn picture(&self, index: u32) -> ApplicationResult<Picture> {
+        let (start, end) = self.range()?;
+        let mut day = end - i64::from(index);
+        for _ in 0..MAX_SKIPPED_DAYS {
+            if day < start {
+                break;
+            }
+            match self.get(&[("date", format_date(day))]).await? {
+                Some(text) => {
+                    let picture = serde_json::from_str::<Picture>(&text)?;
+                    if picture.media_type == "image" {
+                        return Ok(picture);
+                    }
+                    log::trace!(
+                        "Skipping APOD {}, it is a {}",
+                        picture.date,
+                        picture.media_type
+                    );
+                }
+                // usually today's picture not being published yet
+                None => log::trace!("Skipping APOD {}, there is none", format_date(day)),
+            }
+            day -= 1;
+        }
+        Err(ApplicationError::ApiError {
+            e: format!(
+                "No APOD image around {}",
+                format_date(end - i64::from(index))
+            ),
+        })
+    }
+
+    fn to_wallpaper(&self, picture: &Picture) -> Wallpaper {
+        Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: Some(picture.date.clone()),
+            title: Some(picture.title.clone()),
+            creator: picture
+                .copyright
+                .as_ref()
+                .map(|copyright| copyright.trim().to_owned()),
+            permalink: Some(format!(
+                "https://apod.nasa.gov/apod/ap{}.html",
+                picture.date.replace('-', "").get(2..).unwrap_or_default()
+            )),
+            url: picture.hdurl.clone().or_else(|| picture.url.clone()),
+            attribution: None,
+        }
+    }
+
+    async fn save(&self, picture: &Picture, dir: &str) -> ApplicationResult<Wallpaper> {
+        let mut wallpaper = self.to_wallpaper(picture);
+        let url = wallpaper
+            .url
+            .clone()
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: format!("APOD {} has no image url", picture.date),
+            })?;
+        let directory = source_directory(dir, &self.config.folder)?;
+        let stem = directory + &safe_filename(Some(&picture.title), &picture.date);
+        wallpaper.path = download(&url, &stem).await?;
+        Ok(wallpaper)
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for ApodSource {
+    fn name(&self) -> &'static str {
+        "apod"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        let (start, end) = self.range()?;
+        Ok((end - start + 1).max(0) as u32)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        Ok(self.to_wallpaper(&self.picture(index).await?))
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let picture = self.picture(index).await?;
+        self.save(&picture, dir).await
+    }
+
+    // in random mode the API does the picking
+    async fn fetch_shuffled(
+        &self,
+        bag: &mut ShuffleBag,
+        dir: &str,
+    ) -> ApplicationResult<Wallpaper> {
+        if self.config.random {
+            return self.fetch_random(dir).await;
+        }
+        match bag.next(self.count().await?) {
+            Some(index) => self.fetch(index, dir).await,
+            None => Err(ApplicationError::ApiError {
+                e: "The APOD date range is empty".to_owned(),
+            }),
+        }
+    }
+
+    async fn fetch_random(&self, dir: &str) -> ApplicationResult<Wallpaper> {
+        if !self.config.random {
+            let count = self.count().await?;
+            if count == 0 {
+                return Err(ApplicationError::ApiError {
+                    e: "The APOD date range is empty".to_owned(),
+                });
+            }
+            let index = rand::thread_rng().gen_range(0, count);
+            return self.fetch(index, dir).await;
+        }
+        for _ in 0..MAX_SKIPPED_DAYS / RANDOM_BATCH {
+            let text = self
+                .get(&[("count", RANDOM_BATCH.to_string())])
+                .await?
+                .unwrap_or_else(|| "[]".to_owned());
+            let pictures = serde_json::from_str::<Vec<Picture>>(&text)?;
+            if let Some(picture) = pictures
+                .iter()
+                .find(|picture| picture.media_type == "image")
+            {
+                return self.save(picture, dir).await;
+            }
+        }
+        Err(ApplicationError::ApiError {
+            e: "APOD only returned videos".to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use mockito::{Matcher, Server};
+
+    #[test]
+    fn dates_are_counted_from_the_epoch() {
+        assert_eq!(parse_apod_date("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_apod_date("1969-12-31").unwrap(), -1);
+        assert_eq!(parse_apod_date("2000-03-01").unwrap(), 11017);
+        assert_eq!(parse_apod_date(APOD_FIRST_DATE).unwrap(), 9297);
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(11016), "2000-02-29");
+    }
+
+    #[test]
+    fn invalid_dates_are_refused() {
+        for invalid in [
+            "1995-13-01",
+            "1995-06-32",
+            "1995-02-31",
+            "1995-02-29",
+            "1995-06",
+            "yesterday",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    parse_apod_date(invalid),
+                    Err(ApplicationError::ConfigError { .. })
+                ),
+                "{} was accepted",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn today_is_never_ahead_of_utc() {
+        let utc = (Utc::now().date_naive() - epoch()).num_days();
+        assert!(utc - today() <= 1 && today() <= utc);
+    }
+
+    fn picture(date: &str, media_type: &str) -> String {
+        format!(
+            r#"{{"date": "{date}", "title": "Picture of {date}", "media_type": "{media_type}",
+                "url": "https://apod.nasa.gov/{date}.jpg", "hdurl": "https://apod.nasa.gov/{date}-hd.jpg"}}"#
+        )
+    }
+
+    fn source(server: &Server) -> ApodSource {
+        ApodSource::new(&ApodConfig {
+            url: server.url(),
+            api_key: "KEY".to_owned(),
+            start_date: Some("2024-01-01".to_owned()),
+            end_date: Some("2024-01-04".to_owned()),
+            ..ApodConfig::default()
+        })
+    }
+
+    async fn day(server: &mut Server, date: &str, status: usize, body: String) -> mockito::Mock {
+        server
+            .mock("GET", "/planetary/apod")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("api_key".into(), "KEY".into()),
+                Matcher::UrlEncoded("date".into(), date.into()),
+            ]))
+            .with_status(status)
+            .with_body(body)
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn videos_and_unpublished_days_are_skipped() {
+        testing::isolate();
+        let mut server = Server::new_async().await;
+        let after = day(
+            &mut server,
+            "2024-01-04",
+            400,
+            r#"{"code": 400, "msg": "Date must be between Jun 16, 1995 and Jan 03, 2024."}"#
+                .to_owned(),
+        )
+        .await;
+        let video = day(
+            &mut server,
+            "2024-01-03",
+            200,
+            picture("2024-01-03", "video"),
+        )
+        .await;
+        let image = day(
+            &mut server,
+            "2024-01-02",
+            200,
+            picture("2024-01-02", "image"),
+        )
+        .await;
+        let source = source(&server);
+
+        assert_eq!(source.count().await.unwrap(), 4);
+        let wallpaper = source.metadata(0).await.unwrap();
+        assert_eq!(wallpaper.id.as_deref(), Some("2024-01-02"));
+        assert_eq!(wallpaper.title.as_deref(), Some("Picture of 2024-01-02"));
+        assert_eq!(
+            wallpaper.url.as_deref(),
+            Some("https://apod.nasa.gov/2024-01-02-hd.jpg")
+        );
+        assert_eq!(
+            wallpaper.permalink.as_deref(),
+            Some("https://apod.nasa.gov/apod/ap240102.html")
+        );
+        after.assert_async().await;
+        video.assert_async().await;
+        image.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn errors_carry_the_message_of_the_api() {
+        let mut server = Server::new_async().await;
+        day(
+            &mut server,
+            "2024-01-04",
+            500,
+            r#"{"code": 500, "msg": "Internal Service Error"}"#.to_owned(),
+        )
+        .await;
+        day(
+            &mut server,
+            "2024-01-03",
+            403,
+            r#"{"error": {"code": "API_KEY_INVALID", "message": "An invalid api_key was supplied."}}"#
+                .to_owned(),
+        )
+        .await;
+        let source = source(&server);
+
+        match source.metadata(0).await {
+            Err(ApplicationError::ApiError { e }) => assert_eq!(e, "Internal Service Error"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match source.metadata(1).await {
+            Err(error @ ApplicationError::HttpError { status: 403, .. }) => {
+                assert!(!error.is_retryable());
+                assert!(error
+                    .to_string()
+                    .contains("An invalid api_key was supplied."));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use super::{download, safe_filename, source_directory, Wallpaper, WallpaperSource};
+use crate::config::BingConfig;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+pub const URL_BING: &str = "https://www.bing.com";
+pub const BING_RESOLUTIONS: &[&str] = &["UHD", "1920x1080", "1920x1200", "1366x768"];
+// The archive only goes this far back
+pub const BING_MAX_DAYS: u32 = 8;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Image {
+    startdate: String,
+    urlbase: String,
+    copyright: String,
+    #[serde(default)]
+    copyrightlink: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageArchive {
+    images: Vec<Image>,
+}
+
+pub struct BingSource {
+    pub config: BingConfig,
+}
+
+// "Puffins on Skomer, Wales (© Jane Doe/Getty Images)" is split into the
+// title and the creator.
+fn split_copyright(copyright: &str) -> (String, Option<String>) {
+    match copyright.rsplit_once(" (©") {
+        Some((title, creator)) => (
+            title.trim().to_owned(),
+            Some(creator.trim_end_matches(')').trim().to_owned()),
+        ),
+        None => (copyright.trim().to_owned(), None),
+    }
+}
+
+impl BingSource {
+    pub fn new(config: &BingConfig) -> BingSource {
+        BingSource {
+            config: config.clone(),
+        }
+    }
+
+    async fn archive(&self) -> ApplicationResult<Vec<Image>> {
+        let url = format!(
+            "{}/HPImageArchive.aspx",
+            self.config.url.trim_end_matches('/')
+        );
+        let response = reqwest::Client::new()
+            .get(url)
+            .query(&[
+                ("format", "js"),
+                ("idx", "0"),
+                ("n", &self.config.days.to_string()),
+                ("mkt", &self.config.market),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ApplicationError::HttpError {
+                status: response.status().as_u16(),
+                e: format!("Bing answered {}", response.status()),
+            });
+        }
+        let archive = serde_json::from_str::<ImageArchive>(&response.text().await?)?;
+        Ok(archive.images)
+    }
+
+    fn to_wallpaper(&self, image: &Image) -> Wallpaper {
+        let (title, creator) = split_copyright(&image.copyright);
+        Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: Some(image.startdate.clone()),
+            title: match &image.title {
+                Some(short_title) if !short_title.is_empty() => Some(short_title.clone()),
+                _ => Some(title),
+            },
+            creator,
+            permalink: image.copyrightlink.clone(),
+            url: Some(format!(
+                "{}{}_{}.jpg",
+                self.config.url.trim_end_matches('/'),
+                image.urlbase,
+                self.config.resolution
+            )),
+            attribution: Some(image.copyright.clone()),
+        }
+    }
+
+    async fn save(&self, image: &Image, dir: &str) -> ApplicationResult<Wallpaper> {
+        let mut wallpaper = self.to_wallpaper(image);
+        let directory = source_directory(dir, &self.config.folder)?;
+        let id = format!(
+            "{}-{}-{}",
+            image.startdate, self.config.market, self.config.resolution
+        );
+        let stem = directory + &safe_filename(wallpaper.title.as_deref(), &id);
+        wallpaper.path = download(wallpaper.url.as_deref().unwrap_or_default(), &stem).await?;
+        Ok(wallpaper)
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for BingSource {
+    fn name(&self) -> &'static str {
+        "bing"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.archive().await?.len() as u32)
+    }
+
+    // index 0 is today, 1 yesterday and so on
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        match self.archive().await?.get(index as usize) {
+            Some(image) => Ok(self.to_wallpaper(image)),
+            None => Err(ApplicationError::ApiError {
+                e: format!("Bing has no image from {} days ago", index),
+            }),
+        }
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let images = self.archive().await?;
+        let image = images
+            .get(index as usize)
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: format!("Bing has no image from {} days ago", index),
+            })?;
+        let wallpaper = self.save(image, dir).await?;
+        if self.config.backfill {
+            for other in images.iter().filter(|other| *other != image) {
+                if let Err(e) = self.save(other, dir).await {
+                    log::warn!("Cannot backfill Bing image {}: {}", other.startdate, e);
+                }
+            }
+        }
+        Ok(wallpaper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copyright_is_split_into_title_and_creator() {
+        assert_eq!(
+            split_copyright("Lavender fields, Provence (© Jane Doe/Getty Images)"),
+            (
+                "Lavender fields, Provence".to_owned(),
+                Some("Jane Doe/Getty Images".to_owned())
+            )
+        );
+        assert_eq!(
+            split_copyright("A bridge (at night) (© Someone)"),
+            ("A bridge (at night)".to_owned(), Some("Someone".to_owned()))
+        );
+        assert_eq!(
+            split_copyright(" No credit "),
+            ("No credit".to_owned(), None)
+        );
+    }
+}
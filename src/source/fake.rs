@@ -0,0 +1,123 @@
+use super::{source_directory, Wallpaper, WallpaperSource};
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use image::{Rgb, RgbImage};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Generates solid color images locally, to exercise the rotation without
+// any network access. It can also fail on schedule, like a flaky network.
+pub struct FakeSource {
+    pub directory: String,
+    pub count: u32,
+    pub fail_every: Option<u32>,
+    fetches: AtomicU32,
+}
+
+impl FakeSource {
+    pub fn new(dir: &str, count: u32, fail_every: Option<u32>) -> FakeSource {
+        FakeSource {
+            directory: dir.to_owned(),
+            count,
+            fail_every,
+            fetches: AtomicU32::new(0),
+        }
+    }
+
+    fn color(index: u32) -> Rgb<u8> {
+        let [_, r, g, b] = index.wrapping_mul(0x9E37_79B9).to_be_bytes();
+        Rgb([r, g, b])
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for FakeSource {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.count)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        if index >= self.count {
+            return Err(ApplicationError::ApiError {
+                e: format!("Fake wallpaper {} doesn't exist", index),
+            });
+        }
+        Ok(Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: Some(index.to_string()),
+            title: Some(format!("Fake {}", index)),
+            creator: None,
+            permalink: None,
+            url: Some(format!("fake://{}", index)),
+            attribution: None,
+        })
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let fetches = self.fetches.fetch_add(1, Ordering::Relaxed) + 1;
+        if self
+            .fail_every
+            .is_some_and(|fail_every| fetches.is_multiple_of(fail_every))
+        {
+            return Err(ApplicationError::RequestError {
+                e: format!("Fake failure of fetch {}", fetches),
+            });
+        }
+        let mut wallpaper = self.metadata(index).await?;
+        wallpaper.path = source_directory(dir, &self.directory)? + &format!("fake-{}.png", index);
+        if !std::path::Path::new(&wallpaper.path).exists() {
+            RgbImage::from_pixel(64, 36, Self::color(index))
+                .save(&wallpaper.path)
+                .map_err(|e| ApplicationError::IoError { e: e.to_string() })?;
+        }
+        Ok(wallpaper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fetch_draws_a_stable_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let source = FakeSource::new("Fake", 3, None);
+
+        let wallpaper = source.fetch(2, &dir).await.unwrap();
+
+        assert_eq!(source.count().await.unwrap(), 3);
+        assert_eq!(wallpaper.path, format!("{}/Fake/fake-2.png", dir));
+        assert_eq!(wallpaper.key(), "fake://2");
+        let image = image::open(&wallpaper.path).unwrap().to_rgb8();
+        assert_eq!(*image.get_pixel(0, 0), FakeSource::color(2));
+        assert_ne!(FakeSource::color(1), FakeSource::color(2));
+    }
+
+    #[tokio::test]
+    async fn indices_past_the_count_do_not_exist() {
+        let source = FakeSource::new("Fake", 3, None);
+        assert!(matches!(
+            source.metadata(3).await,
+            Err(ApplicationError::ApiError { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn fail_every_fails_on_schedule() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let source = FakeSource::new("Fake", 3, Some(3));
+
+        let mut outcomes = Vec::new();
+        for _ in 0..7 {
+            outcomes.push(source.fetch(0, &dir).await.is_ok());
+        }
+
+        assert_eq!(outcomes, [true, true, false, true, true, false, true]);
+    }
+}
@@ -0,0 +1,348 @@
+use super::{download, safe_filename, source_directory, stable_hash, Wallpaper, WallpaperSource};
+use crate::config::FeedConfig;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use feed_rs::model::Entry;
+
+fn is_image_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit_once('.').is_some_and(|(_, extension)| {
+        ["jpg", "jpeg", "png", "webp", "gif"].contains(&extension.to_lowercase().as_str())
+    })
+}
+
+// The src of the first <img> in some html
+fn first_img(html: &str) -> Option<String> {
+    let tag = &html[html.find("<img")?..];
+    let tag = &tag[..tag.find('>')?];
+    let src = &tag[tag.find("src=")? + 4..];
+    let quote = src.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let src = &src[1..];
+    Some(src[..src.find(quote)?].replace("&amp;", "&"))
+}
+
+// media:content (or an enclosure, which feed-rs reads the same way), then
+// an enclosure link, then the first <img> of the description.
+fn image_of(entry: &Entry) -> Option<String> {
+    let media = entry
+        .media
+        .iter()
+        .flat_map(|object| &object.content)
+        .filter(|content| match &content.content_type {
+            Some(content_type) => content_type.to_string().starts_with("image/"),
+            None => content
+                .url
+                .as_ref()
+                .is_some_and(|url| is_image_url(url.as_str())),
+        })
+        // the biggest when several sizes are offered
+        .max_by_key(|content| content.width.unwrap_or_default())
+        .and_then(|content| content.url.as_ref())
+        .map(|url| url.to_string());
+    let enclosure = || {
+        entry
+            .links
+            .iter()
+            .find(|link| {
+                link.rel.as_deref() == Some("enclosure")
+                    && link
+                        .media_type
+                        .as_deref()
+                        .is_some_and(|media_type| media_type.starts_with("image/"))
+            })
+            .map(|link| link.href.clone())
+    };
+    let img = || {
+        entry
+            .content
+            .as_ref()
+            .and_then(|content| content.body.as_deref())
+            .and_then(first_img)
+            .or_else(|| {
+                entry
+                    .summary
+                    .as_ref()
+                    .and_then(|summary| first_img(&summary.content))
+            })
+    };
+    media.or_else(enclosure).or_else(img)
+}
+
+pub struct FeedSource {
+    pub config: FeedConfig,
+}
+
+impl FeedSource {
+    pub fn new(config: &FeedConfig) -> FeedSource {
+        FeedSource {
+            config: config.clone(),
+        }
+    }
+
+    // The entries with an image, in the order of the feed
+    async fn entries(&self) -> ApplicationResult<Vec<(Entry, String)>> {
+        let response = reqwest::get(&self.config.url).await?;
+        if !response.status().is_success() {
+            return Err(ApplicationError::HttpError {
+                status: response.status().as_u16(),
+                e: format!("{} answered {}", self.config.url, response.status()),
+            });
+        }
+        let bytes = response.bytes().await?;
+        let feed = feed_rs::parser::parse(&bytes[..])
+            .map_err(|e| ApplicationError::DeserializationError { e: e.to_string() })?;
+        Ok(feed
+            .entries
+            .into_iter()
+            .filter_map(|entry| match image_of(&entry) {
+                Some(url) => Some((entry, url)),
+                None => {
+                    log::trace!("Skipping feed entry {}, it has no image", entry.id);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    async fn entry(&self, index: u32) -> ApplicationResult<(Entry, String)> {
+        self.entries()
+            .await?
+            .into_iter()
+            .nth(index as usize)
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: format!("{} has no image entry {}", self.config.url, index),
+            })
+    }
+
+    fn to_wallpaper(&self, entry: &Entry, url: &str) -> Wallpaper {
+        let credit = entry
+            .media
+            .iter()
+            .flat_map(|object| &object.credits)
+            .map(|credit| credit.entity.clone())
+            .next();
+        Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: Some(entry.id.clone()),
+            title: entry.title.as_ref().map(|title| title.content.clone()),
+            creator: entry
+                .authors
+                .first()
+                .map(|author| author.name.clone())
+                .or(credit),
+            permalink: entry
+                .links
+                .iter()
+                .find(|link| link.rel.as_deref().unwrap_or("alternate") == "alternate")
+                .map(|link| link.href.clone()),
+            url: Some(url.to_owned()),
+            attribution: entry.rights.as_ref().map(|rights| rights.content.clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for FeedSource {
+    fn name(&self) -> &'static str {
+        "feed"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.entries().await?.len() as u32)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        let (entry, url) = self.entry(index).await?;
+        Ok(self.to_wallpaper(&entry, &url))
+    }
+
+    // named after the GUID, so an entry is only downloaded once
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let (entry, url) = self.entry(index).await?;
+        let mut wallpaper = self.to_wallpaper(&entry, &url);
+        let directory = source_directory(dir, &self.config.folder)?;
+        let hash = format!("{:016x}", stable_hash(&entry.id));
+        let stem = directory + &safe_filename(wallpaper.title.as_deref(), &hash);
+        wallpaper.path = download(&url, &stem).await?;
+        Ok(wallpaper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Wallpapers</title>
+    <link>https://example.org/</link>
+    <item>
+      <title>Dunes at dusk</title>
+      <link>https://example.org/dunes</link>
+      <guid>https://example.org/?p=1</guid>
+      <author>ann@example.org (Ann)</author>
+      <enclosure url="https://example.org/dunes.jpg" length="12345" type="image/jpeg"/>
+    </item>
+    <item>
+      <title>A text only post</title>
+      <link>https://example.org/news</link>
+      <guid>https://example.org/?p=2</guid>
+      <description>Nothing to see</description>
+    </item>
+    <item>
+      <title>Hills</title>
+      <link>https://example.org/hills</link>
+      <guid>https://example.org/?p=3</guid>
+      <description><![CDATA[<p>Green</p><img src="https://example.org/hills.png" alt="hills">]]></description>
+    </item>
+    <item>
+      <title>A podcast</title>
+      <guid>https://example.org/?p=4</guid>
+      <enclosure url="https://example.org/episode.mp3" length="1" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Wallpapers</title>
+  <id>urn:uuid:60a76c80-d399-11d9-b91C-0003939e0af6</id>
+  <updated>2024-01-02T10:00:00Z</updated>
+  <entry>
+    <title>Glacier</title>
+    <id>urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a</id>
+    <updated>2024-01-02T10:00:00Z</updated>
+    <author><name>Bob</name></author>
+    <link rel="alternate" href="https://example.org/glacier"/>
+    <link rel="enclosure" type="image/webp" href="https://example.org/glacier.webp"/>
+  </entry>
+</feed>"#;
+
+    const MEDIA_RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/">
+  <channel>
+    <title>Photos</title>
+    <link>https://example.org/</link>
+    <item>
+      <title>Harbour</title>
+      <link>https://example.org/harbour</link>
+      <guid>harbour</guid>
+      <media:content url="https://example.org/harbour-small.jpg" type="image/jpeg" width="640"/>
+      <media:content url="https://example.org/harbour-large.jpg" type="image/jpeg" width="3840"/>
+      <media:content url="https://example.org/harbour.mp4" type="video/mp4" width="7680"/>
+      <media:credit>Carol</media:credit>
+    </item>
+  </channel>
+</rss>"#;
+
+    async fn serve(server: &mut Server, path: &str, feed: &str) -> FeedSource {
+        server
+            .mock("GET", path)
+            .with_header("content-type", "application/xml")
+            .with_body(feed)
+            .create_async()
+            .await;
+        FeedSource::new(&FeedConfig {
+            url: format!("{}{}", server.url(), path),
+            ..FeedConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn rss_enclosures_and_description_images_are_found() {
+        let mut server = Server::new_async().await;
+        let source = serve(&mut server, "/rss", RSS).await;
+
+        assert_eq!(source.count().await.unwrap(), 2);
+        let dunes = source.metadata(0).await.unwrap();
+        assert_eq!(dunes.title.as_deref(), Some("Dunes at dusk"));
+        assert_eq!(dunes.url.as_deref(), Some("https://example.org/dunes.jpg"));
+        assert_eq!(
+            dunes.permalink.as_deref(),
+            Some("https://example.org/dunes")
+        );
+        assert_eq!(dunes.id.as_deref(), Some("https://example.org/?p=1"));
+        let hills = source.metadata(1).await.unwrap();
+        assert_eq!(hills.title.as_deref(), Some("Hills"));
+        assert_eq!(hills.url.as_deref(), Some("https://example.org/hills.png"));
+        assert_eq!(
+            hills.permalink.as_deref(),
+            Some("https://example.org/hills")
+        );
+        assert!(source.metadata(2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn atom_enclosure_links_are_found() {
+        let mut server = Server::new_async().await;
+        let source = serve(&mut server, "/atom", ATOM).await;
+
+        assert_eq!(source.count().await.unwrap(), 1);
+        let glacier = source.metadata(0).await.unwrap();
+        assert_eq!(glacier.title.as_deref(), Some("Glacier"));
+        assert_eq!(
+            glacier.url.as_deref(),
+            Some("https://example.org/glacier.webp")
+        );
+        assert_eq!(
+            glacier.permalink.as_deref(),
+            Some("https://example.org/glacier")
+        );
+        assert_eq!(glacier.creator.as_deref(), Some("Bob"));
+    }
+
+    #[tokio::test]
+    async fn the_largest_media_content_image_is_picked() {
+        let mut server = Server::new_async().await;
+        let source = serve(&mut server, "/media", MEDIA_RSS).await;
+
+        let harbour = source.metadata(0).await.unwrap();
+        assert_eq!(harbour.title.as_deref(), Some("Harbour"));
+        assert_eq!(
+            harbour.url.as_deref(),
+            Some("https://example.org/harbour-large.jpg")
+        );
+        assert_eq!(
+            harbour.permalink.as_deref(),
+            Some("https://example.org/harbour")
+        );
+        assert_eq!(harbour.creator.as_deref(), Some("Carol"));
+    }
+
+    #[tokio::test]
+    async fn a_broken_feed_is_a_data_error() {
+        let mut server = Server::new_async().await;
+        let source = serve(&mut server, "/broken", "<rss><channel>").await;
+        assert!(matches!(
+            source.count().await,
+            Err(ApplicationError::DeserializationError { .. })
+        ));
+    }
+
+    #[test]
+    fn first_img_reads_quoted_sources() {
+        assert_eq!(
+            first_img("<p>Hi</p><img alt=\"x\" src=\"https://a.org/1.jpg?a=1&amp;b=2\"><img src=\"2.jpg\">"),
+            Some("https://a.org/1.jpg?a=1&b=2".to_owned())
+        );
+        assert_eq!(
+            first_img("<img src='https://a.org/1.png'/>"),
+            Some("https://a.org/1.png".to_owned())
+        );
+        assert_eq!(first_img("<img src=https://a.org/1.png>"), None);
+        assert_eq!(first_img("<img alt=\"no source\">"), None);
+        assert_eq!(first_img("<p>no image</p>"), None);
+        assert_eq!(first_img("<img src=\"unterminated"), None);
+    }
+
+    #[test]
+    fn image_urls_are_recognized_by_extension() {
+        assert!(is_image_url("https://a.org/1.JPG"));
+        assert!(is_image_url("https://a.org/1.webp?size=large#top"));
+        assert!(!is_image_url("https://a.org/page.html"));
+        assert!(!is_image_url("https://a.org/image?format=.jpg"));
+    }
+}
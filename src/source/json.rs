@@ -0,0 +1,309 @@
+use super::{download, safe_filename, source_directory, Wallpaper, WallpaperSource};
+use crate::config::JsonConfig;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use jsonpath_rust::parser::parse_json_path;
+use jsonpath_rust::JsonPath;
+use serde_json::Value;
+
+pub fn check_path(path: &str) -> ApplicationResult<()> {
+    parse_json_path(path)
+        .map(|_| ())
+        .map_err(|e| ApplicationError::ConfigError {
+            e: format!("Invalid JSONPath '{}': {}", path, e),
+        })
+}
+
+fn query<'a>(path: &str, value: &'a Value) -> Vec<&'a Value> {
+    value.query(path).unwrap_or_default()
+}
+
+// The first match as a string, numbers included since ids often are
+fn query_string(path: &str, value: &Value) -> Option<String> {
+    match query(path, value).first()? {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+fn encode_component(component: &str) -> String {
+    let mut encoded = String::new();
+    for byte in component.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Any API listing wallpapers as JSON, described by a URL template and
+// JSONPath expressions in the configuration.
+pub struct JsonSource {
+    pub config: JsonConfig,
+}
+
+impl JsonSource {
+    pub fn new(config: &JsonConfig) -> JsonSource {
+        JsonSource {
+            config: config.clone(),
+        }
+    }
+
+    fn get_url_for_offset(&self, offset: u32) -> String {
+        self.config
+            .url
+            .replace("{offset}", &offset.to_string())
+            .replace("{limit}", &self.config.limit.to_string())
+            .replace(
+                "{query}",
+                &encode_component(self.config.query.as_deref().unwrap_or_default()),
+            )
+    }
+
+    async fn get_wallpaper_list(&self, offset: u32) -> ApplicationResult<Value> {
+        let response = reqwest::get(self.get_url_for_offset(offset)).await?;
+        if !response.status().is_success() {
+            return Err(ApplicationError::HttpError {
+                status: response.status().as_u16(),
+                e: format!("{} answered {}", self.config.url, response.status()),
+            });
+        }
+        Ok(serde_json::from_str::<Value>(&response.text().await?)?)
+    }
+
+    // pages start at a multiple of the limit, like simpledesktops' offset
+    async fn get_item(&self, index: u32) -> ApplicationResult<Value> {
+        let offset = index - index % self.config.limit;
+        let list = self.get_wallpaper_list(offset).await?;
+        query(&self.config.items, &list)
+            .get((index - offset) as usize)
+            .map(|item| (*item).clone())
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: format!("{} has no wallpaper {}", self.config.url, index),
+            })
+    }
+
+    fn to_wallpaper(&self, item: &Value) -> ApplicationResult<Wallpaper> {
+        let config = &self.config;
+        let url = query_string(&config.image, item).ok_or_else(|| ApplicationError::ApiError {
+            e: format!("'{}' matches no image url", self.config.image),
+        })?;
+        let field =
+            |path: &Option<String>| path.as_deref().and_then(|path| query_string(path, item));
+        Ok(Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: field(&config.id),
+            title: field(&config.title),
+            creator: field(&config.author),
+            permalink: field(&config.permalink),
+            url: Some(url),
+            attribution: None,
+        })
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for JsonSource {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    // without a total only the first page is used
+    async fn count(&self) -> ApplicationResult<u32> {
+        let list = self.get_wallpaper_list(0).await?;
+        match &self.config.total {
+            Some(total) => match query(total, &list).first() {
+                Some(Value::Number(number)) => Ok(number.as_u64().unwrap_or_default() as u32),
+                Some(Value::String(string)) => {
+                    string
+                        .trim()
+                        .parse()
+                        .map_err(|_| ApplicationError::DeserializationError {
+                            e: format!("'{}' matches '{}', not a number", total, string),
+                        })
+                }
+                _ => Err(ApplicationError::ApiError {
+                    e: format!("'{}' matches no total", total),
+                }),
+            },
+            None => Ok(query(&self.config.items, &list).len() as u32),
+        }
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        self.to_wallpaper(&self.get_item(index).await?)
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let mut wallpaper = self.to_wallpaper(&self.get_item(index).await?)?;
+        let url = wallpaper.url.clone().unwrap_or_default();
+        let filename = url
+            .split(['?', '#'])
+            .next()
+            .and_then(|url| url.rsplit('/').next())
+            .filter(|filename| !filename.is_empty())
+            .map(|filename| filename.to_owned())
+            .unwrap_or_else(|| format!("{}.jpg", index));
+        let directory = source_directory(dir, &self.config.folder)?;
+        // the name of the remote file identifies it when there is no id
+        let id = wallpaper.id.clone().unwrap_or_else(|| {
+            filename
+                .rsplit_once('.')
+                .map_or(filename.clone(), |(stem, _)| stem.to_owned())
+        });
+        let stem = directory + &safe_filename(wallpaper.title.as_deref(), &id);
+        wallpaper.path = download(&url, &stem).await?;
+        Ok(wallpaper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{Matcher, Server};
+
+    async fn count(total: &str) -> ApplicationResult<u32> {
+        let mut server = Server::new_async().await;
+        let _list = server
+            .mock("GET", "/list")
+            .match_query(Matcher::Any)
+            .with_body(format!(r#"{{"total": {}, "items": []}}"#, total))
+            .create_async()
+            .await;
+        JsonSource::new(&JsonConfig {
+            url: format!("{}/list?offset={{offset}}", server.url()),
+            items: "$.items[*]".to_owned(),
+            total: Some("$.total".to_owned()),
+            ..JsonConfig::default()
+        })
+        .count()
+        .await
+    }
+
+    #[tokio::test]
+    async fn totals_can_be_numbers_or_strings() {
+        assert_eq!(count("57").await.unwrap(), 57);
+        assert_eq!(count(r#""57""#).await.unwrap(), 57);
+    }
+
+    #[tokio::test]
+    async fn a_total_that_is_not_a_number_is_a_data_error() {
+        match count(r#""many""#).await {
+            Err(ApplicationError::DeserializationError { e }) => {
+                assert!(e.contains("$.total"), "{}", e);
+                assert!(e.contains("many"), "{}", e);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(
+            count("null").await,
+            Err(ApplicationError::ApiError { .. })
+        ));
+    }
+
+    fn config(server: &Server) -> JsonConfig {
+        JsonConfig {
+            url: format!(
+                "{}/search?q={{query}}&offset={{offset}}&limit={{limit}}",
+                server.url()
+            ),
+            query: Some("sunny day/ü".to_owned()),
+            limit: 2,
+            items: "$.data.items[*]".to_owned(),
+            image: "$.img.src".to_owned(),
+            id: Some("$.id".to_owned()),
+            title: Some("$.name".to_owned()),
+            author: Some("$.by.name".to_owned()),
+            permalink: Some("$.page".to_owned()),
+            ..JsonConfig::default()
+        }
+    }
+
+    #[test]
+    fn the_url_template_is_filled_in() {
+        let source = JsonSource::new(&JsonConfig {
+            url: "https://example.org/search?q={query}&offset={offset}&limit={limit}".to_owned(),
+            query: Some("sunny day/ü".to_owned()),
+            limit: 2,
+            ..JsonConfig::default()
+        });
+        assert_eq!(
+            source.get_url_for_offset(4),
+            "https://example.org/search?q=sunny%20day%2F%C3%BC&offset=4&limit=2"
+        );
+        let source = JsonSource::new(&JsonConfig {
+            url: "https://example.org/all?q={query}".to_owned(),
+            ..JsonConfig::default()
+        });
+        assert_eq!(source.get_url_for_offset(0), "https://example.org/all?q=");
+    }
+
+    #[tokio::test]
+    async fn items_are_found_on_their_page_and_mapped() {
+        let mut server = Server::new_async().await;
+        let page = server
+            .mock("GET", "/search")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("q".into(), "sunny day/ü".into()),
+                Matcher::UrlEncoded("offset".into(), "2".into()),
+                Matcher::UrlEncoded("limit".into(), "2".into()),
+            ]))
+            .with_body(
+                r#"{"data": {"items": [
+                    {"id": "c", "img": {"src": "https://example.org/c.jpg"}},
+                    {"id": 7, "name": "Dunes", "by": {"name": "Ann"},
+                     "page": "https://example.org/7", "img": {"src": "https://example.org/7.jpg"}}
+                ]}}"#,
+            )
+            .expect(2)
+            .create_async()
+            .await;
+        let source = JsonSource::new(&config(&server));
+
+        let wallpaper = source.metadata(3).await.unwrap();
+        assert_eq!(
+            wallpaper,
+            Wallpaper {
+                path: String::new(),
+                source: Some("json".to_owned()),
+                id: Some("7".to_owned()),
+                title: Some("Dunes".to_owned()),
+                creator: Some("Ann".to_owned()),
+                permalink: Some("https://example.org/7".to_owned()),
+                url: Some("https://example.org/7.jpg".to_owned()),
+                attribution: None,
+            }
+        );
+        let first = source.metadata(2).await.unwrap();
+        assert_eq!(first.id.as_deref(), Some("c"));
+        assert_eq!(first.title, None);
+        assert_eq!(first.creator, None);
+        page.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn items_without_an_image_are_refused() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/search")
+            .match_query(Matcher::Any)
+            .with_body(r#"{"data": {"items": [{"id": 1, "img": {}}]}}"#)
+            .create_async()
+            .await;
+        let source = JsonSource::new(&config(&server));
+        assert!(matches!(
+            source.metadata(0).await,
+            Err(ApplicationError::ApiError { .. })
+        ));
+        // past the end of the page
+        assert!(matches!(
+            source.metadata(1).await,
+            Err(ApplicationError::ApiError { .. })
+        ));
+    }
+}
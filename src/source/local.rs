@@ -0,0 +1,271 @@
+use super::{sniff_extension, Wallpaper, WallpaperSource, IMAGE_EXTENSIONS};
+use crate::config::LocalConfig;
+use crate::shuffle::ShuffleBag;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rand::Rng;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use walkdir::WalkDir;
+
+// Rotates through images already on disk. The directory is scanned again on
+// every call so added and removed files are picked up between changes.
+pub struct LocalSource {
+    pub config: LocalConfig,
+    scan: Arc<Scan>,
+}
+
+pub fn glob_set(patterns: &[String]) -> ApplicationResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(
+            Glob::new(pattern).map_err(|e| ApplicationError::ConfigError {
+                e: format!("Invalid glob '{}': {}", pattern, e),
+            })?,
+        );
+    }
+    builder
+        .build()
+        .map_err(|e| ApplicationError::ConfigError { e: e.to_string() })
+}
+
+// The directory walk, run on a blocking thread since it opens every file
+struct Scan {
+    root: PathBuf,
+    recursive: bool,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Scan {
+    // Looks at the first bytes rather than trusting the extension
+    fn is_image(path: &Path) -> bool {
+        sniff_extension(path)
+            .ok()
+            .flatten()
+            .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension))
+    }
+
+    // sorted so an index means the same file as long as nothing changes
+    fn run(&self) -> ApplicationResult<Vec<String>> {
+        let root = self.root.as_path();
+        if !root.is_dir() {
+            return Err(ApplicationError::IoError {
+                e: format!("'{}' is not a directory", root.display()),
+            });
+        }
+        let max_depth = if self.recursive { usize::MAX } else { 1 };
+        let mut files = WalkDir::new(root)
+            .max_depth(max_depth)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    log::warn!("Cannot read {}: {}", root.display(), e);
+                    None
+                }
+            })
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| {
+                let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                self.include
+                    .as_ref()
+                    .is_none_or(|include| include.is_match(relative))
+                    && !self.exclude.is_match(relative)
+            })
+            .filter(|entry| Self::is_image(entry.path()))
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+}
+
+impl LocalSource {
+    pub fn new(config: &LocalConfig) -> ApplicationResult<LocalSource> {
+        Ok(LocalSource {
+            config: config.clone(),
+            scan: Arc::new(Scan {
+                root: PathBuf::from(&config.path),
+                recursive: config.recursive,
+                include: if config.include.is_empty() {
+                    None
+                } else {
+                    Some(glob_set(&config.include)?)
+                },
+                exclude: glob_set(&config.exclude)?,
+            }),
+        })
+    }
+
+    async fn scan(&self) -> ApplicationResult<Vec<String>> {
+        let scan = self.scan.clone();
+        tokio::task::spawn_blocking(move || scan.run())
+            .await
+            .map_err(|e| ApplicationError::IoError { e: e.to_string() })?
+    }
+
+    fn to_wallpaper(&self, path: &str) -> Wallpaper {
+        let path = Path::new(path);
+        Wallpaper {
+            path: path.to_string_lossy().into_owned(),
+            source: Some(self.name().to_owned()),
+            id: Some(
+                path.strip_prefix(&self.config.path)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            title: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            creator: None,
+            permalink: None,
+            url: None,
+            attribution: None,
+        }
+    }
+
+    fn get(&self, files: &[String], index: u32) -> ApplicationResult<Wallpaper> {
+        files
+            .get(index as usize)
+            .map(|path| self.to_wallpaper(path))
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: format!("'{}' has no image {}", self.config.path, index),
+            })
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for LocalSource {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.scan().await?.len() as u32)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        self.get(&self.scan().await?, index)
+    }
+
+    // nothing to download, the image is used where it is
+    async fn fetch(&self, index: u32, _dir: &str) -> ApplicationResult<Wallpaper> {
+        self.get(&self.scan().await?, index)
+    }
+
+    // a single scan, so a file removed in between can't be picked
+    async fn fetch_random(&self, _dir: &str) -> ApplicationResult<Wallpaper> {
+        let files = self.scan().await?;
+        if files.is_empty() {
+            return Err(ApplicationError::ApiError {
+                e: format!("'{}' has no images", self.config.path),
+            });
+        }
+        let index = rand::thread_rng().gen_range(0, files.len() as u32);
+        self.get(&files, index)
+    }
+
+    async fn fetch_shuffled(
+        &self,
+        bag: &mut ShuffleBag,
+        _dir: &str,
+    ) -> ApplicationResult<Wallpaper> {
+        let files = self.scan().await?;
+        match bag.next(files.len() as u32) {
+            Some(index) => self.get(&files, index),
+            None => Err(ApplicationError::ApiError {
+                e: format!("'{}' has no images", self.config.path),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+    use std::fs;
+
+    // a.png, b.jpg holding a png, notes.jpg holding text, sub/c.gif and
+    // sub/skip/d.png
+    fn library() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let image = RgbImage::new(2, 2);
+        fs::create_dir_all(dir.path().join("sub/skip")).unwrap();
+        image.save(dir.path().join("a.png")).unwrap();
+        image
+            .save_with_format(dir.path().join("b.jpg"), image::ImageFormat::Png)
+            .unwrap();
+        fs::write(dir.path().join("notes.jpg"), "not an image").unwrap();
+        image.save(dir.path().join("sub/c.gif")).unwrap();
+        image.save(dir.path().join("sub/skip/d.png")).unwrap();
+        dir
+    }
+
+    async fn files(dir: &tempfile::TempDir, config: LocalConfig) -> Vec<String> {
+        let source = LocalSource::new(&LocalConfig {
+            path: dir.path().to_string_lossy().into_owned(),
+            ..config
+        })
+        .unwrap();
+        let mut files = Vec::new();
+        for index in 0..source.count().await.unwrap() {
+            files.push(source.metadata(index).await.unwrap().id.unwrap());
+        }
+        files
+    }
+
+    #[tokio::test]
+    async fn only_images_are_found_whatever_their_extension() {
+        let dir = library();
+        assert_eq!(
+            files(&dir, LocalConfig::default()).await,
+            ["a.png", "b.jpg", "sub/c.gif", "sub/skip/d.png"]
+        );
+    }
+
+    #[tokio::test]
+    async fn globs_and_recursion_filter_the_files() {
+        let dir = library();
+        assert_eq!(
+            files(
+                &dir,
+                LocalConfig {
+                    recursive: false,
+                    ..LocalConfig::default()
+                }
+            )
+            .await,
+            ["a.png", "b.jpg"]
+        );
+        assert_eq!(
+            files(
+                &dir,
+                LocalConfig {
+                    include: vec!["**/*.png".to_owned()],
+                    exclude: vec!["sub/skip/**".to_owned()],
+                    ..LocalConfig::default()
+                }
+            )
+            .await,
+            ["a.png"]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_missing_directory_is_an_error() {
+        let source = LocalSource::new(&LocalConfig {
+            path: "/nonexistent/pictures".to_owned(),
+            ..LocalConfig::default()
+        })
+        .unwrap();
+        assert!(matches!(
+            source.count().await,
+            Err(ApplicationError::IoError { .. })
+        ));
+    }
+}
@@ -0,0 +1,344 @@
+use super::{from_config, stable_hash, Wallpaper, WallpaperSource};
+use crate::config::WeightedSource;
+use crate::shuffle;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Each failure in a row halves the weight of a source, up to this many times
+const MAX_PENALTY: u32 = 6;
+// A source that hasn't failed for this long gets its full weight back
+const PENALTY_DURATION: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Default)]
+struct Health {
+    failures: u32,
+    last_failure: Option<Instant>,
+}
+
+struct Slot {
+    config: WeightedSource,
+    // built again on the next use when building it failed
+    source: Mutex<Option<Arc<dyn WallpaperSource>>>,
+    health: Mutex<Health>,
+}
+
+impl Slot {
+    async fn source(&self) -> ApplicationResult<Arc<dyn WallpaperSource>> {
+        if let Some(source) = self.source.lock().unwrap().as_ref() {
+            return Ok(source.clone());
+        }
+        let source: Arc<dyn WallpaperSource> = Arc::from(from_config(&self.config.source).await?);
+        *self.source.lock().unwrap() = Some(source.clone());
+        Ok(source)
+    }
+
+    // identifies the source in the persisted state
+    fn key(&self) -> ApplicationResult<String> {
+        let config = serde_json::to_string(&self.config.source)?;
+        Ok(format!(
+            "{}-{:016x}",
+            self.config.source.folder(),
+            stable_hash(&config)
+        ))
+    }
+
+    fn weight(&self) -> f64 {
+        let mut health = self.health.lock().unwrap();
+        if health
+            .last_failure
+            .is_some_and(|last_failure| last_failure.elapsed() > PENALTY_DURATION)
+        {
+            *health = Health::default();
+        }
+        self.config.weight / f64::from(1 << health.failures.min(MAX_PENALTY))
+    }
+
+    fn failed(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.failures += 1;
+        health.last_failure = Some(Instant::now());
+    }
+
+    fn succeeded(&self) {
+        *self.health.lock().unwrap() = Health::default();
+    }
+}
+
+// Picks a source by weight for every random wallpaper, and falls back to the
+// others when it fails. Indices go through the sources one after another.
+pub struct MixedSource {
+    slots: Vec<Slot>,
+    seed: Option<u64>,
+    // seeded like the shuffle bags, so the picks can be reproduced too
+    rng: Mutex<StdRng>,
+}
+
+impl MixedSource {
+    pub async fn new(configs: &[WeightedSource], seed: Option<u64>) -> MixedSource {
+        let mut slots = Vec::new();
+        for config in configs {
+            let source = match from_config(&config.source).await {
+                Ok(source) => Some(Arc::from(source)),
+                Err(e) => {
+                    log::warn!(
+                        "Cannot use the {} source yet: {}",
+                        config.source.folder(),
+                        e
+                    );
+                    None
+                }
+            };
+            slots.push(Slot {
+                config: config.clone(),
+                source: Mutex::new(source),
+                health: Mutex::new(Health::default()),
+            });
+        }
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        MixedSource {
+            slots,
+            seed,
+            rng: Mutex::new(rng),
+        }
+    }
+
+    // The source holding wallpaper `index` and its index within that source
+    async fn locate(&self, index: u32) -> ApplicationResult<(Arc<dyn WallpaperSource>, u32)> {
+        let mut index = index;
+        for slot in &self.slots {
+            let source = slot.source().await?;
+            let count = source.count().await?;
+            if index < count {
+                return Ok((source, index));
+            }
+            index -= count;
+        }
+        Err(ApplicationError::ApiError {
+            e: format!("Wallpaper {} doesn't exist", index),
+        })
+    }
+
+    // Each source goes through its own shuffle bag, kept across restarts
+    async fn fetch_random_from(
+        &self,
+        slot: &Slot,
+        directory: &str,
+    ) -> ApplicationResult<Wallpaper> {
+        let source = slot.source().await?;
+        let key = slot.key()?;
+        // so that sources sharing the configured seed are shuffled differently
+        let seed = self.seed.map(|seed| seed ^ stable_hash(&key));
+        let mut bag = shuffle::load(&key, seed)?;
+        let wallpaper = source.fetch_shuffled(&mut bag, directory).await;
+        // even after a failure, a broken wallpaper mustn't block the others
+        if let Err(e) = shuffle::save(&key, &bag) {
+            log::warn!("Cannot save the shuffled order: {}", e);
+        }
+        wallpaper
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for MixedSource {
+    fn name(&self) -> &'static str {
+        "mix"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        let mut count = 0;
+        for slot in &self.slots {
+            count += slot.source().await?.count().await?;
+        }
+        Ok(count)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        let (source, index) = self.locate(index).await?;
+        source.metadata(index).await
+    }
+
+    async fn fetch(&self, index: u32, directory: &str) -> ApplicationResult<Wallpaper> {
+        let (source, index) = self.locate(index).await?;
+        source.fetch(index, directory).await
+    }
+
+    async fn fetch_random(&self, directory: &str) -> ApplicationResult<Wallpaper> {
+        let mut remaining: Vec<&Slot> = self.slots.iter().collect();
+        let mut last_error = ApplicationError::ConfigError {
+            e: "No source is configured".to_owned(),
+        };
+        while !remaining.is_empty() {
+            let weights: Vec<f64> = remaining.iter().map(|slot| slot.weight()).collect();
+            let mut pick = self
+                .rng
+                .lock()
+                .unwrap()
+                .gen_range(0.0, weights.iter().sum::<f64>());
+            let mut chosen = remaining.len() - 1;
+            for (position, weight) in weights.iter().enumerate() {
+                if pick < *weight {
+                    chosen = position;
+                    break;
+                }
+                pick -= weight;
+            }
+            let slot = remaining.remove(chosen);
+            match self.fetch_random_from(slot, directory).await {
+                Ok(wallpaper) => {
+                    slot.succeeded();
+                    return Ok(wallpaper);
+                }
+                Err(e) => {
+                    if !remaining.is_empty() {
+                        log::warn!(
+                            "The {} source failed, trying another one: {}",
+                            slot.config.source.folder(),
+                            e
+                        );
+                    }
+                    slot.failed();
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SourceConfig;
+    use crate::testing;
+
+    fn fake(folder: &str, weight: f64, fail_every: Option<u32>) -> WeightedSource {
+        WeightedSource {
+            weight,
+            source: SourceConfig::Fake {
+                folder: folder.to_owned(),
+                count: 3,
+                fail_every,
+            },
+        }
+    }
+
+    // The folder of the source that served each of `picks` wallpapers
+    async fn served(source: &MixedSource, directory: &str, picks: usize) -> Vec<String> {
+        let mut served = Vec::new();
+        for _ in 0..picks {
+            let wallpaper = source.fetch_random(directory).await.unwrap();
+            let folder = wallpaper
+                .path
+                .strip_prefix(directory)
+                .and_then(|path| path.trim_start_matches('/').split('/').next())
+                .unwrap();
+            served.push(folder.to_owned());
+        }
+        served
+    }
+
+    #[tokio::test]
+    async fn sources_are_picked_by_weight() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let source = MixedSource::new(
+            &[fake("Heavy", 3.0, None), fake("Light", 1.0, None)],
+            Some(1),
+        )
+        .await;
+        let served = served(&source, &dir, 400).await;
+        let heavy = served.iter().filter(|folder| *folder == "Heavy").count();
+        assert!((260..340).contains(&heavy), "{} out of 400", heavy);
+    }
+
+    #[tokio::test]
+    async fn a_seed_gives_the_same_picks() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let configs = [
+            fake("A", 1.0, None),
+            fake("B", 1.0, None),
+            fake("C", 1.0, None),
+        ];
+        let first = served(&MixedSource::new(&configs, Some(7)).await, &dir, 30).await;
+        let again = served(&MixedSource::new(&configs, Some(7)).await, &dir, 30).await;
+        assert_eq!(first, again);
+        assert!(first.iter().any(|folder| folder != &first[0]));
+    }
+
+    #[tokio::test]
+    async fn a_failing_source_falls_back_to_the_others_and_is_penalized() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let source = MixedSource::new(
+            &[fake("Broken", 1000.0, Some(1)), fake("Working", 1.0, None)],
+            Some(2),
+        )
+        .await;
+
+        // picked first almost every time, it never serves anything
+        assert_eq!(served(&source, &dir, 3).await, ["Working"; 3]);
+        let broken = &source.slots[0];
+        assert_eq!(broken.health.lock().unwrap().failures, 3);
+        assert_eq!(broken.weight(), 1000.0 / 8.0);
+        assert_eq!(source.slots[1].weight(), 1.0);
+
+        // the penalty stops growing
+        served(&source, &dir, 10).await;
+        assert_eq!(broken.weight(), 1000.0 / f64::from(1 << MAX_PENALTY));
+    }
+
+    #[tokio::test]
+    async fn penalties_wear_off() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let source = MixedSource::new(&[fake("Flaky", 1.0, Some(2))], Some(3)).await;
+        let flaky = &source.slots[0];
+
+        // every other fetch fails, there is nothing to fall back to
+        source.fetch_random(&dir).await.unwrap();
+        assert!(source.fetch_random(&dir).await.is_err());
+        assert_eq!(flaky.weight(), 0.5);
+        // a success gives the full weight back
+        source.fetch_random(&dir).await.unwrap();
+        assert_eq!(flaky.weight(), 1.0);
+
+        assert!(source.fetch_random(&dir).await.is_err());
+        flaky.health.lock().unwrap().last_failure =
+            Instant::now().checked_sub(PENALTY_DURATION + Duration::from_secs(1));
+        assert_eq!(flaky.weight(), 1.0);
+        assert_eq!(flaky.health.lock().unwrap().failures, 0);
+    }
+
+    #[tokio::test]
+    async fn the_last_error_is_reported_when_every_source_fails() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let source =
+            MixedSource::new(&[fake("A", 1.0, Some(1)), fake("B", 1.0, Some(1))], Some(4)).await;
+        assert!(matches!(
+            source.fetch_random(&dir).await,
+            Err(ApplicationError::RequestError { .. })
+        ));
+        assert!(source
+            .slots
+            .iter()
+            .all(|slot| slot.health.lock().unwrap().failures == 1));
+        assert!(matches!(
+            MixedSource::new(&[], None).await.fetch_random(&dir).await,
+            Err(ApplicationError::ConfigError { .. })
+        ));
+    }
+}
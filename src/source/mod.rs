@@ -0,0 +1,624 @@
+use crate::config::{SourceConfig, WeightedSource};
+use crate::dirs;
+use crate::shuffle::ShuffleBag;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use rand::Rng;
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+mod apod;
+mod bing;
+mod fake;
+mod feed;
+mod json;
+mod local;
+mod mix;
+mod reddit;
+mod simpledesktops;
+mod unsplash;
+mod wallhaven;
+
+pub use apod::{parse_apod_date, ApodSource, APOD_FIRST_DATE, URL_APOD};
+pub use bing::{BingSource, BING_MAX_DAYS, BING_RESOLUTIONS, URL_BING};
+pub use fake::FakeSource;
+pub use feed::FeedSource;
+pub use json::{check_path, JsonSource};
+pub use local::{glob_set, LocalSource};
+pub use mix::MixedSource;
+pub use reddit::{RedditSource, REDDIT_LISTINGS, REDDIT_MAX_LIMIT, REDDIT_TIMES, URL_REDDIT};
+pub use simpledesktops::{migrate_legacy_names, SimpleWallpaper, URL_DESKTOP};
+pub use unsplash::{UnsplashSource, UNSPLASH_ORIENTATIONS, URL_UNSPLASH};
+pub use wallhaven::{
+    WallhavenSource, URL_WALLHAVEN, WALLHAVEN_CATEGORIES, WALLHAVEN_PURITIES, WALLHAVEN_SORTINGS,
+    WALLHAVEN_TOP_RANGES,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Wallpaper {
+    pub path: String,
+    #[serde(default)]
+    pub source: Option<String>,
+    // identifier of the wallpaper within its source
+    #[serde(default)]
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub creator: Option<String>,
+    pub permalink: Option<String>,
+    pub url: Option<String>,
+    // credit the provider requires to be shown with the image
+    #[serde(default)]
+    pub attribution: Option<String>,
+}
+
+impl Wallpaper {
+    // identifies the same wallpaper across downloads
+    pub fn key(&self) -> &str {
+        self.url.as_deref().unwrap_or(&self.path)
+    }
+}
+
+#[async_trait]
+pub trait WallpaperSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // number of wallpapers, valid indices are 0..count
+    async fn count(&self) -> ApplicationResult<u32>;
+
+    // everything about a wallpaper except its local path
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper>;
+
+    // downloads the wallpaper into `directory` unless it is already there
+    async fn fetch(&self, index: u32, directory: &str) -> ApplicationResult<Wallpaper>;
+
+    async fn fetch_random(&self, directory: &str) -> ApplicationResult<Wallpaper> {
+        let count = self.count().await?;
+        if count == 0 {
+            return Err(ApplicationError::ApiError {
+                e: format!("The {} source has no wallpapers", self.name()),
+            });
+        }
+        let index = rand::thread_rng().gen_range(0, count);
+        self.fetch(index, directory).await
+    }
+
+    // like fetch_random, but every wallpaper comes once before any repeats
+    async fn fetch_shuffled(
+        &self,
+        bag: &mut ShuffleBag,
+        directory: &str,
+    ) -> ApplicationResult<Wallpaper> {
+        match bag.next(self.count().await?) {
+            Some(index) => self.fetch(index, directory).await,
+            None => Err(ApplicationError::ApiError {
+                e: format!("The {} source has no wallpapers", self.name()),
+            }),
+        }
+    }
+}
+
+pub async fn from_config(config: &SourceConfig) -> ApplicationResult<Box<dyn WallpaperSource>> {
+    match config {
+        SourceConfig::SimpleDesktops { folder, url } => {
+            Ok(Box::new(SimpleWallpaper::new(folder, url).await?))
+        }
+        SourceConfig::Fake {
+            folder,
+            count,
+            fail_every,
+        } => Ok(Box::new(FakeSource::new(folder, *count, *fail_every))),
+        SourceConfig::Unsplash(unsplash) => Ok(Box::new(UnsplashSource::new(unsplash)?)),
+        SourceConfig::Bing(bing) => Ok(Box::new(BingSource::new(bing))),
+        SourceConfig::Apod(apod) => Ok(Box::new(ApodSource::new(apod))),
+        SourceConfig::Wallhaven(wallhaven) => Ok(Box::new(WallhavenSource::new(wallhaven).await?)),
+        SourceConfig::Reddit(reddit) => Ok(Box::new(RedditSource::new(reddit)?)),
+        SourceConfig::Local(local) => Ok(Box::new(LocalSource::new(local)?)),
+        SourceConfig::Json(json) => Ok(Box::new(JsonSource::new(json))),
+        SourceConfig::Feed(feed) => Ok(Box::new(FeedSource::new(feed))),
+    }
+}
+
+// All the configured sources behind one, picked by weight
+pub async fn from_sources(
+    configs: &[WeightedSource],
+    seed: Option<u64>,
+) -> Box<dyn WallpaperSource> {
+    Box::new(MixedSource::new(configs, seed).await)
+}
+
+// FNV-1a, stable across builds unlike the standard library hasher
+pub fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn source_directory(dir: &str, folder: &str) -> ApplicationResult<String> {
+    let directory = String::from(dir) + "/" + folder + "/";
+    fs::create_dir_all(&directory)?;
+    Ok(directory)
+}
+
+// Suffix of downloads in progress, renamed once complete and verified
+pub const PARTIAL_SUFFIX: &str = ".part";
+// Extensions a cached wallpaper can have
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp", "gif", "bmp"];
+// Keeps the whole filename well below the usual 255 bytes limit
+const MAX_SLUG_LENGTH: usize = 80;
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+// Lowercase ascii letters and digits separated by single dashes
+fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let mut end = slug.len().min(MAX_SLUG_LENGTH);
+    while !slug.is_char_boundary(end) {
+        end -= 1;
+    }
+    slug[..end].trim_end_matches('-').to_owned()
+}
+
+// A filename without extension that is valid on every platform and can't
+// leave the source folder, e.g. "Dunes: 2/3?" with id 42 gives "dunes-2-3-42".
+// The id keeps wallpapers with the same title apart.
+pub fn safe_filename(title: Option<&str>, id: &str) -> String {
+    let name = match (title.map(slug).filter(|title| !title.is_empty()), slug(id)) {
+        (Some(title), id) if !id.is_empty() => format!("{}-{}", title, id),
+        (Some(title), _) => title,
+        (None, id) if !id.is_empty() => id,
+        (None, _) => "wallpaper".to_owned(),
+    };
+    if WINDOWS_RESERVED_NAMES.contains(&name.as_str()) {
+        format!("{}-wallpaper", name)
+    } else {
+        name
+    }
+}
+
+// The cached wallpaper saved under `stem` with any image extension
+pub fn cached(stem: &str) -> Option<String> {
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|extension| format!("{}.{}", stem, extension))
+        .find(|filename| Path::new(filename).is_file())
+}
+
+fn content_type_extension(content_type: &str) -> Option<&'static str> {
+    match content_type.split(';').next()?.trim() {
+        "image/jpeg" | "image/jpg" | "image/pjpeg" => Some("jpg"),
+        "image/png" => Some("png"),
+        "image/webp" => Some("webp"),
+        "image/gif" => Some("gif"),
+        "image/bmp" => Some("bmp"),
+        _ => None,
+    }
+}
+
+// The format read from the first bytes of a file, ignoring its extension
+fn sniff_extension<P: AsRef<Path>>(filename: P) -> ApplicationResult<Option<&'static str>> {
+    let mut header = Vec::new();
+    File::open(filename)?.take(64).read_to_end(&mut header)?;
+    Ok(image::guess_format(&header)
+        .ok()
+        .and_then(|format| format.extensions_str().first().copied()))
+}
+
+// Moves a wallpaper cached under the name an older version gave it to
+// `stem`, along with its sidecar. Files that aren't images, like the empty
+// leftovers of failed downloads, are removed instead.
+pub fn migrate(legacy: &str, stem: &str) -> ApplicationResult<()> {
+    // old names built from titles could point anywhere
+    if Path::new(legacy).parent() != Path::new(stem).parent() {
+        return Ok(());
+    }
+    // a legacy name can already be safe, the file still needs checking
+    let legacy_is_cached = cached(stem).is_some_and(|filename| filename == legacy);
+    if !Path::new(legacy).is_file() || cached(stem).is_some() && !legacy_is_cached {
+        return Ok(());
+    }
+    match sniff_extension(legacy)? {
+        Some(extension) => {
+            let filename = format!("{}.{}", stem, extension);
+            if filename == legacy {
+                return Ok(());
+            }
+            log::info!("Renaming '{}' to '{}'", legacy, filename);
+            fs::rename(legacy, &filename)?;
+            let sidecar = legacy.to_owned() + ".json";
+            if Path::new(&sidecar).is_file() {
+                fs::rename(&sidecar, filename + ".json")?;
+            }
+        }
+        None => {
+            log::info!("Removing '{}', it is not an image", legacy);
+            fs::remove_file(legacy)?;
+        }
+    }
+    Ok(())
+}
+
+// Where downloads in progress are kept
+pub fn partial_downloads_dir() -> ApplicationResult<String> {
+    Ok(dirs::cache_dir()? + "/downloads")
+}
+
+// The cache can be on another file system than the pictures, the file is
+// then copied next to its destination first so it still appears at once.
+fn move_into_place(partial: &str, filename: &str) -> ApplicationResult<()> {
+    if fs::rename(partial, filename).is_ok() {
+        return Ok(());
+    }
+    let staging = filename.to_owned() + PARTIAL_SUFFIX;
+    let result = fs::copy(partial, &staging).and_then(|_| fs::rename(&staging, filename));
+    let _ = fs::remove_file(partial);
+    if result.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    result?;
+    Ok(())
+}
+
+// Downloads to `stem` plus the extension of the image, unless it is already
+// cached. The download goes to a temporary file in the cache directory first,
+// so an interrupted or broken download is never mistaken for a wallpaper.
+pub async fn download(url: &str, stem: &str) -> ApplicationResult<String> {
+    if let Some(filename) = cached(stem) {
+        return Ok(filename);
+    }
+    let directory = partial_downloads_dir()?;
+    fs::create_dir_all(&directory)?;
+    let partial = format!("{}/{:016x}{}", directory, stable_hash(stem), PARTIAL_SUFFIX);
+    let result = download_to(url, &partial).await;
+    match result {
+        Ok(extension) => {
+            let filename = format!("{}.{}", stem, extension);
+            move_into_place(&partial, &filename)?;
+            log::trace!("Downloaded wallpaper at '{}'", filename);
+            Ok(filename)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+// The extension of the downloaded image, from its content whatever the
+// server says it is
+async fn download_to(url: &str, partial: &str) -> ApplicationResult<&'static str> {
+    let mut response = reqwest::get(url).await?;
+    if !response.status().is_success() {
+        return Err(ApplicationError::HttpError {
+            status: response.status().as_u16(),
+            e: format!("{} answered {}", url, response.status()),
+        });
+    }
+    let expected = response.content_length();
+    let content_type_extension = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(content_type_extension);
+    let mut file = tokio::fs::File::create(partial).await?;
+    let mut received = 0;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+        received += chunk.len() as u64;
+    }
+    file.sync_all().await?;
+    if let Some(expected) = expected {
+        if received != expected {
+            return Err(ApplicationError::RequestError {
+                e: format!("{} sent {} bytes out of {}", url, received, expected),
+            });
+        }
+    }
+    let partial = partial.to_owned();
+    let url = url.to_owned();
+    tokio::task::spawn_blocking(move || {
+        let reader = image::ImageReader::open(&partial)?.with_guessed_format()?;
+        let sniffed = match reader.format() {
+            Some(format) => format.extensions_str().first().copied().unwrap_or("jpg"),
+            None => {
+                return Err(ApplicationError::ApiError {
+                    e: format!("{} is not an image", url),
+                })
+            }
+        };
+        reader.decode().map_err(|e| ApplicationError::ApiError {
+            e: format!("{} is not a valid image: {}", url, e),
+        })?;
+        // the decoded format is the one that was verified
+        if let Some(announced) = content_type_extension.filter(|&announced| announced != sniffed) {
+            log::debug!("{} is served as {} but is a {}", url, announced, sniffed);
+        }
+        Ok(sniffed)
+    })
+    .await
+    .map_err(|e| ApplicationError::IoError { e: e.to_string() })?
+}
+
+// Leftovers of downloads interrupted by a crash or a shutdown, `dir` being
+// the partial downloads directory or the folder of a source
+pub fn remove_partial_downloads(dir: &str) -> ApplicationResult<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_file() && path.to_string_lossy().ends_with(PARTIAL_SUFFIX) {
+            log::debug!("Removing the partial download '{}'", path.display());
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+// Keeps the metadata next to the image, e.g. `photo.jpg.json`
+pub fn write_sidecar(wallpaper: &Wallpaper) -> ApplicationResult<()> {
+    fs::write(
+        wallpaper.path.clone() + ".json",
+        serde_json::to_string_pretty(wallpaper)?,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slug_keeps_lowercase_ascii_words() {
+        assert_eq!(slug("Dunes: 2/3?"), "dunes-2-3");
+        assert_eq!(slug("  --Hello,  World!-- "), "hello-world");
+        assert_eq!(slug("Été à Paris"), "t-paris");
+        assert_eq!(slug("日本"), "");
+        assert_eq!(slug("../../etc/passwd"), "etc-passwd");
+    }
+
+    #[test]
+    fn slug_is_truncated_without_a_trailing_dash() {
+        let long = "a".repeat(MAX_SLUG_LENGTH - 1) + " bcd";
+        let slug = slug(&long);
+        assert_eq!(slug, "a".repeat(MAX_SLUG_LENGTH - 1));
+        assert!(slug.len() <= MAX_SLUG_LENGTH);
+    }
+
+    #[test]
+    fn safe_filename_combines_title_and_id() {
+        assert_eq!(safe_filename(Some("Dunes: 2/3?"), "42"), "dunes-2-3-42");
+        assert_eq!(safe_filename(Some("Dunes"), ""), "dunes");
+        assert_eq!(safe_filename(Some("???"), "x/Y"), "x-y");
+        assert_eq!(safe_filename(None, "42"), "42");
+        assert_eq!(safe_filename(None, ""), "wallpaper");
+        assert_eq!(safe_filename(Some(".."), ".."), "wallpaper");
+    }
+
+    #[test]
+    fn safe_filename_avoids_reserved_windows_names() {
+        assert_eq!(safe_filename(Some("CON"), ""), "con-wallpaper");
+        assert_eq!(safe_filename(None, "lpt1"), "lpt1-wallpaper");
+        assert_eq!(safe_filename(Some("Con"), "1"), "con-1");
+    }
+
+    #[test]
+    fn stable_hash_is_fnv1a() {
+        assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(stable_hash("ab"), stable_hash("ba"));
+    }
+
+    #[test]
+    fn content_types_give_extensions() {
+        assert_eq!(content_type_extension("image/jpeg"), Some("jpg"));
+        assert_eq!(
+            content_type_extension("image/png; charset=binary"),
+            Some("png")
+        );
+        assert_eq!(content_type_extension("text/html"), None);
+    }
+
+    #[test]
+    fn cached_finds_any_image_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let stem = dir.path().join("dunes-42").to_string_lossy().into_owned();
+        assert_eq!(cached(&stem), None);
+        fs::write(stem.clone() + ".json", "{}").unwrap();
+        assert_eq!(cached(&stem), None);
+        fs::write(stem.clone() + ".webp", "").unwrap();
+        assert_eq!(cached(&stem), Some(stem + ".webp"));
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(2, 2)
+            .write_to(&mut bytes, image::ImageFormat::Jpeg)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn migrate_renames_by_content_with_the_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("Dunes.png").to_string_lossy().into_owned();
+        let stem = dir.path().join("dunes-42").to_string_lossy().into_owned();
+        fs::write(&legacy, jpeg()).unwrap();
+        fs::write(legacy.clone() + ".json", "{}").unwrap();
+        migrate(&legacy, &stem).unwrap();
+        assert!(!Path::new(&legacy).exists());
+        assert_eq!(cached(&stem), Some(stem.clone() + ".jpg"));
+        assert!(Path::new(&(stem + ".jpg.json")).is_file());
+    }
+
+    #[test]
+    fn migrate_removes_what_is_not_an_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("Dunes.png").to_string_lossy().into_owned();
+        let stem = dir.path().join("dunes-42").to_string_lossy().into_owned();
+        fs::write(&legacy, "").unwrap();
+        migrate(&legacy, &stem).unwrap();
+        assert!(!Path::new(&legacy).exists());
+        assert_eq!(cached(&stem), None);
+    }
+
+    #[test]
+    fn migrate_keeps_what_is_already_cached_or_elsewhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("Dunes.png").to_string_lossy().into_owned();
+        let stem = dir.path().join("dunes-42").to_string_lossy().into_owned();
+        fs::write(&legacy, jpeg()).unwrap();
+        fs::write(stem.clone() + ".png", "").unwrap();
+        migrate(&legacy, &stem).unwrap();
+        assert!(Path::new(&legacy).is_file());
+
+        let elsewhere = dir.path().join("sub/dunes").to_string_lossy().into_owned();
+        migrate(&legacy, &elsewhere).unwrap();
+        assert!(Path::new(&legacy).is_file());
+    }
+
+    #[tokio::test]
+    async fn downloads_are_named_after_their_content() {
+        crate::testing::isolate();
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/dunes.png")
+            .with_header("content-type", "image/png")
+            .with_body(jpeg())
+            .create_async()
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let stem = dir.path().join("dunes-42").to_string_lossy().into_owned();
+        let filename = download(&format!("{}/dunes.png", server.url()), &stem)
+            .await
+            .unwrap();
+        assert_eq!(filename, stem + ".jpg");
+        assert!(Path::new(&filename).is_file());
+        mock.assert_async().await;
+    }
+
+    fn partial(stem: &str) -> String {
+        format!(
+            "{}/{:016x}{}",
+            partial_downloads_dir().unwrap(),
+            stable_hash(stem),
+            PARTIAL_SUFFIX
+        )
+    }
+
+    // Serves `response` as is to the first connection
+    fn raw_server(response: &'static [u8]) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            use std::io::Write;
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let _ = stream.write_all(response);
+        });
+        format!("http://{}/dunes.jpg", address)
+    }
+
+    #[tokio::test]
+    async fn a_short_body_is_not_kept() {
+        crate::testing::isolate();
+        let url = raw_server(
+            b"HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: 1000\r\n\r\nonly a few bytes",
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let stem = dir.path().join("short").to_string_lossy().into_owned();
+        assert!(matches!(
+            download(&url, &stem).await,
+            Err(ApplicationError::RequestError { .. })
+        ));
+        assert_eq!(cached(&stem), None);
+        assert!(!Path::new(&partial(&stem)).exists());
+    }
+
+    #[tokio::test]
+    async fn what_doesnt_decode_is_not_kept() {
+        crate::testing::isolate();
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/page.jpg")
+            .with_header("content-type", "image/jpeg")
+            .with_body("<html>Not found</html>")
+            .create_async()
+            .await;
+        let mut truncated = jpeg();
+        truncated.truncate(truncated.len() / 2);
+        server
+            .mock("GET", "/truncated.jpg")
+            .with_header("content-type", "image/jpeg")
+            .with_body(truncated)
+            .create_async()
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+
+        for name in ["page", "truncated"] {
+            let stem = dir.path().join(name).to_string_lossy().into_owned();
+            let result = download(&format!("{}/{}.jpg", server.url(), name), &stem).await;
+            assert!(
+                matches!(result, Err(ApplicationError::ApiError { .. })),
+                "{}: {:?}",
+                name,
+                result
+            );
+            assert_eq!(cached(&stem), None);
+            assert!(!Path::new(&partial(&stem)).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_keep_the_http_status() {
+        crate::testing::isolate();
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/gone.jpg")
+            .with_status(404)
+            .create_async()
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let stem = dir.path().join("gone").to_string_lossy().into_owned();
+        assert!(matches!(
+            download(&format!("{}/gone.jpg", server.url()), &stem).await,
+            Err(ApplicationError::HttpError { status: 404, .. })
+        ));
+        assert!(!Path::new(&partial(&stem)).exists());
+    }
+
+    #[test]
+    fn only_partial_downloads_are_cleaned_up() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("0123456789abcdef.part"), "").unwrap();
+        fs::write(dir.path().join("dunes-42.jpg"), "").unwrap();
+        fs::write(dir.path().join("dunes-42.jpg.json"), "{}").unwrap();
+        fs::create_dir(dir.path().join("nested.part")).unwrap();
+
+        remove_partial_downloads(&dir.path().to_string_lossy()).unwrap();
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["dunes-42.jpg", "dunes-42.jpg.json", "nested.part"]);
+
+        remove_partial_downloads(&dir.path().join("missing").to_string_lossy()).unwrap();
+    }
+}
@@ -0,0 +1,329 @@
+use super::{download, safe_filename, source_directory, Wallpaper, WallpaperSource};
+use crate::config::{parse_size, RedditConfig};
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+pub const URL_REDDIT: &str = "https://www.reddit.com";
+pub const REDDIT_LISTINGS: &[&str] = &["hot", "new", "top", "rising"];
+pub const REDDIT_TIMES: &[&str] = &["hour", "day", "week", "month", "year", "all"];
+// Reddit never returns more than this per listing page
+pub const REDDIT_MAX_LIMIT: u32 = 100;
+// Reddit throttles the default user agents of http libraries
+const USER_AGENT: &str = concat!("rusty-wallpaper/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Post {
+    id: String,
+    title: String,
+    author: String,
+    permalink: String,
+    url: String,
+    subreddit: String,
+    #[serde(default)]
+    over_18: bool,
+    #[serde(default)]
+    is_video: bool,
+    #[serde(default)]
+    is_gallery: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Child {
+    data: Post,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingData {
+    children: Vec<Child>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    data: ListingData,
+}
+
+// Finds the "[3840x2160]" (or "[3840 × 2160]") most wallpaper
+// subreddits require in titles.
+pub fn parse_title_resolution(title: &str) -> Option<(u32, u32)> {
+    title.split('[').skip(1).find_map(|part| {
+        let (inside, _) = part.split_once(']')?;
+        let inside: String = inside
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c == '×' || c == 'X' { 'x' } else { c })
+            .collect();
+        parse_size(&inside)
+    })
+}
+
+// The direct image behind a post, None for anything that isn't a plain
+// picture on i.redd.it or imgur.
+fn image_url(url: &str) -> Option<String> {
+    let url = url.split(['?', '#']).next()?;
+    let (_, rest) = url.split_once("://")?;
+    let (host, path) = rest.split_once('/')?;
+    let has_image_extension = |path: &str| {
+        path.rsplit_once('.').is_some_and(|(_, extension)| {
+            ["jpg", "jpeg", "png"].contains(&extension.to_lowercase().as_str())
+        })
+    };
+    match host.trim_start_matches("www.").trim_start_matches("m.") {
+        "i.redd.it" | "i.imgur.com" if has_image_extension(path) => Some(url.to_owned()),
+        // a page for a single image, albums and galleries are under a/ and gallery/
+        "imgur.com" if !path.is_empty() && !path.contains('/') && !path.contains('.') => {
+            Some(format!("https://i.imgur.com/{}.jpg", path))
+        }
+        _ => None,
+    }
+}
+
+pub struct RedditSource {
+    pub config: RedditConfig,
+    client: reqwest::Client,
+}
+
+impl RedditSource {
+    pub fn new(config: &RedditConfig) -> ApplicationResult<RedditSource> {
+        Ok(RedditSource {
+            config: config.clone(),
+            client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+        })
+    }
+
+    // The posts of the listing that are usable as wallpapers
+    async fn posts(&self) -> ApplicationResult<Vec<(Post, String)>> {
+        let config = &self.config;
+        let url = format!(
+            "{}/r/{}/{}.json",
+            config.url.trim_end_matches('/'),
+            config.subreddits.join("+"),
+            config.listing
+        );
+        let response = self
+            .client
+            .get(url)
+            .query(&[
+                ("t", config.time.clone()),
+                ("limit", config.limit.to_string()),
+                ("raw_json", "1".to_owned()),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ApplicationError::HttpError {
+                status: response.status().as_u16(),
+                e: format!("Reddit answered {}", response.status()),
+            });
+        }
+        let listing = serde_json::from_str::<Listing>(&response.text().await?)?;
+        let minimum = config.min_resolution.as_deref().and_then(parse_size);
+
+        Ok(listing
+            .data
+            .children
+            .into_iter()
+            .filter_map(|child| {
+                let post = child.data;
+                if post.over_18 || post.is_video || post.is_gallery {
+                    log::trace!("Skipping reddit post {}, not a single SFW image", post.id);
+                    return None;
+                }
+                if let Some((min_width, min_height)) = minimum {
+                    match parse_title_resolution(&post.title) {
+                        Some((width, height)) if width >= min_width && height >= min_height => {}
+                        _ => {
+                            log::trace!("Skipping reddit post {}, too small", post.id);
+                            return None;
+                        }
+                    }
+                }
+                let url = image_url(&post.url)?;
+                Some((post, url))
+            })
+            .collect())
+    }
+
+    async fn post(&self, index: u32) -> ApplicationResult<(Post, String)> {
+        self.posts()
+            .await?
+            .into_iter()
+            .nth(index as usize)
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: format!("Reddit has no image post at index {}", index),
+            })
+    }
+
+    fn to_wallpaper(&self, post: &Post, url: &str) -> Wallpaper {
+        Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: Some(post.id.clone()),
+            title: Some(post.title.clone()),
+            creator: Some(format!("u/{}", post.author)),
+            permalink: Some(format!(
+                "{}{}",
+                self.config.url.trim_end_matches('/'),
+                post.permalink
+            )),
+            url: Some(url.to_owned()),
+            attribution: Some(format!(
+                "Posted by u/{} on r/{}",
+                post.author, post.subreddit
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for RedditSource {
+    fn name(&self) -> &'static str {
+        "reddit"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.posts().await?.len() as u32)
+    }
+
+    // in the order of the listing
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        let (post, url) = self.post(index).await?;
+        Ok(self.to_wallpaper(&post, &url))
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let (post, url) = self.post(index).await?;
+        let mut wallpaper = self.to_wallpaper(&post, &url);
+        let directory = source_directory(dir, &self.config.folder)?;
+        let stem = directory + &safe_filename(Some(&post.title), &post.id);
+        wallpaper.path = download(&url, &stem).await?;
+        Ok(wallpaper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolutions_are_read_from_brackets() {
+        assert_eq!(
+            parse_title_resolution("Misty lake [3840x2160]"),
+            Some((3840, 2160))
+        );
+        assert_eq!(
+            parse_title_resolution("[OC] Dunes [2560 × 1440] by me"),
+            Some((2560, 1440))
+        );
+        assert_eq!(
+            parse_title_resolution("Road [1920X1080]"),
+            Some((1920, 1080))
+        );
+        assert_eq!(parse_title_resolution("No size here"), None);
+        assert_eq!(parse_title_resolution("[OC] [wide]"), None);
+        assert_eq!(parse_title_resolution("Unclosed [1920x1080"), None);
+    }
+
+    #[test]
+    fn only_direct_images_and_single_imgur_pages_are_kept() {
+        assert_eq!(
+            image_url("https://i.redd.it/abc.jpg?width=100"),
+            Some("https://i.redd.it/abc.jpg".to_owned())
+        );
+        assert_eq!(
+            image_url("https://i.imgur.com/abc.PNG"),
+            Some("https://i.imgur.com/abc.PNG".to_owned())
+        );
+        assert_eq!(
+            image_url("https://imgur.com/abc"),
+            Some("https://i.imgur.com/abc.jpg".to_owned())
+        );
+        assert_eq!(image_url("https://imgur.com/a/abc"), None);
+        assert_eq!(image_url("https://imgur.com/gallery/abc"), None);
+        assert_eq!(image_url("https://i.redd.it/abc.gif"), None);
+        assert_eq!(image_url("https://v.redd.it/abc"), None);
+        assert_eq!(image_url("https://example.com/abc.jpg"), None);
+        assert_eq!(image_url("not a url"), None);
+    }
+
+    fn post(id: &str, title: &str, url: &str, flags: &str) -> String {
+        format!(
+            r#"{{"kind": "t3", "data": {{"id": "{id}", "title": "{title}", "author": "ann",
+                "permalink": "/r/wallpapers/comments/{id}/", "url": "{url}",
+                "subreddit": "wallpapers"{flags}}}}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn only_single_sfw_images_are_listed() {
+        let mut server = mockito::Server::new_async().await;
+        let children = [
+            post("a1", "Dunes [3840x2160]", "https://i.redd.it/a1.jpg", ""),
+            post(
+                "b2",
+                "Nsfw [3840x2160]",
+                "https://i.redd.it/b2.jpg",
+                r#", "over_18": true"#,
+            ),
+            post(
+                "c3",
+                "Album [3840x2160]",
+                "https://www.reddit.com/gallery/c3",
+                r#", "is_gallery": true"#,
+            ),
+            post(
+                "d4",
+                "Clip [3840x2160]",
+                "https://v.redd.it/d4",
+                r#", "is_video": true"#,
+            ),
+            post(
+                "e5",
+                "Article [3840x2160]",
+                "https://example.com/e5.html",
+                "",
+            ),
+            post("f6", "Small [1280x720]", "https://i.imgur.com/f6.png", ""),
+            post("g7", "Hills [2560x1440]", "https://imgur.com/g7", ""),
+        ];
+        let listing = server
+            .mock("GET", "/r/wallpapers+earthporn/top.json")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("t".into(), "week".into()),
+                mockito::Matcher::UrlEncoded("limit".into(), "50".into()),
+            ]))
+            .match_header(
+                "user-agent",
+                mockito::Matcher::Regex("^rusty-wallpaper/".into()),
+            )
+            .with_body(format!(
+                r#"{{"kind": "Listing", "data": {{"children": [{}]}}}}"#,
+                children.join(",")
+            ))
+            .expect(3)
+            .create_async()
+            .await;
+        let source = RedditSource::new(&RedditConfig {
+            url: server.url(),
+            subreddits: vec!["wallpapers".to_owned(), "earthporn".to_owned()],
+            limit: 50,
+            min_resolution: Some("1920x1080".to_owned()),
+            ..RedditConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(source.count().await.unwrap(), 2);
+        let dunes = source.metadata(0).await.unwrap();
+        assert_eq!(dunes.id.as_deref(), Some("a1"));
+        assert_eq!(dunes.url.as_deref(), Some("https://i.redd.it/a1.jpg"));
+        assert_eq!(dunes.creator.as_deref(), Some("u/ann"));
+        assert_eq!(
+            dunes.permalink,
+            Some(format!("{}/r/wallpapers/comments/a1/", server.url()))
+        );
+        let hills = source.metadata(1).await.unwrap();
+        assert_eq!(hills.id.as_deref(), Some("g7"));
+        assert_eq!(hills.url.as_deref(), Some("https://i.imgur.com/g7.jpg"));
+        listing.assert_async().await;
+    }
+}
@@ -0,0 +1,222 @@
+use super::{
+    cached, download, migrate, safe_filename, slug, source_directory, Wallpaper, WallpaperSource,
+};
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+pub const URL_DESKTOP: &str =
+    "http://api.simpledesktops.com/v1/desktop_mobile/?format=json&limit=1";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Meta {
+    limit: u32,
+    next: Option<String>,
+    offset: u32,
+    previous: Option<String>,
+    total_count: u32,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Creator {
+    email: Option<String>,
+    name: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Object {
+    creator: Creator,
+    id: String,
+    iphone_thumb: String,
+    permalink: String,
+    title: String,
+    url: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonWallpaperList {
+    meta: Meta,
+    objects: Vec<Object>,
+}
+
+pub struct SimpleWallpaper {
+    pub total_count: u32,
+    pub directory: String,
+    pub url: String,
+}
+
+impl SimpleWallpaper {
+    pub async fn new(dir: &str, url: &str) -> ApplicationResult<SimpleWallpaper> {
+        match Self::get_wallpaper_list(url, 0).await {
+            Ok(wallpaper_list) => Ok(SimpleWallpaper {
+                total_count: wallpaper_list.meta.total_count,
+                directory: dir.to_owned(),
+                url: url.to_owned(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_wallpaper_list(url: &str, offset: u32) -> ApplicationResult<JsonWallpaperList> {
+        let text = reqwest::get(Self::get_url_for_offset(url, offset))
+            .await?
+            .text()
+            .await?;
+        serde_json::from_str::<JsonWallpaperList>(&text)
+            .map_err(|ref e| ApplicationError::DeserializationError { e: e.to_string() })
+    }
+
+    fn get_url_for_offset(url: &str, offset: u32) -> String {
+        format!("{}&offset={}", url, offset)
+    }
+}
+
+// Name without id given to a wallpaper saved as `<title>.png` by older versions
+fn legacy_stem(title: &str) -> Option<String> {
+    Some(title)
+        .filter(|title| !slug(title).is_empty())
+        .map(|title| safe_filename(Some(title), ""))
+}
+
+// Older versions saved wallpapers under their bare title, which can't be
+// trusted in a filename. Gives them safe names once at startup, a later
+// fetch of the same wallpaper adds its id.
+pub fn migrate_legacy_names(directory: &str) -> ApplicationResult<()> {
+    if !Path::new(directory).is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new("png")) || !path.is_file() {
+            continue;
+        }
+        if let Some(stem) = path
+            .file_stem()
+            .and_then(OsStr::to_str)
+            .and_then(legacy_stem)
+        {
+            let stem = Path::new(directory).join(stem);
+            migrate(&path.to_string_lossy(), &stem.to_string_lossy())?;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl WallpaperSource for SimpleWallpaper {
+    fn name(&self) -> &'static str {
+        "simpledesktops"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.total_count)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        let wallpaper_list = Self::get_wallpaper_list(&self.url, index).await?;
+        match wallpaper_list.objects.into_iter().next() {
+            Some(object) => Ok(Wallpaper {
+                path: String::new(),
+                source: Some(self.name().to_owned()),
+                id: Some(object.id),
+                title: Some(object.title),
+                creator: object.creator.name,
+                permalink: Some(object.permalink),
+                url: Some(object.url),
+                attribution: None,
+            }),
+            None => Err(ApplicationError::ApiError {
+                e: "The list of objects retrieved is less than 1".to_owned(),
+            }),
+        }
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let mut wallpaper = self.metadata(index).await?;
+        let sd_directory = source_directory(dir, &self.directory)?;
+        let stem = sd_directory.clone()
+            + &safe_filename(
+                wallpaper.title.as_deref(),
+                wallpaper.id.as_deref().unwrap_or_default(),
+            );
+        // renamed at startup, the id it lacks is only known now
+        if let Some(legacy) = wallpaper.title.as_deref().and_then(legacy_stem) {
+            if let Some(legacy) = cached(&(sd_directory + &legacy)) {
+                migrate(&legacy, &stem)?;
+            }
+        }
+        wallpaper.path = download(wallpaper.url.as_deref().unwrap_or_default(), &stem).await?;
+        Ok(wallpaper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(2, 2)
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    fn png() -> Vec<u8> {
+        encode(image::ImageFormat::Png)
+    }
+
+    fn jpeg() -> Vec<u8> {
+        encode(image::ImageFormat::Jpeg)
+    }
+
+    #[test]
+    fn legacy_names_are_made_safe_at_startup() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().to_string_lossy().into_owned();
+        fs::write(dir.path().join("Sunny Day: 2.png"), png()).unwrap();
+        fs::write(dir.path().join("Sunny Day: 2.png.json"), "{}").unwrap();
+        fs::write(dir.path().join("broken.png"), "").unwrap();
+        fs::write(dir.path().join("???.png"), png()).unwrap();
+        fs::write(dir.path().join("hills-7.png"), png()).unwrap();
+        fs::write(dir.path().join("dunes.png"), jpeg()).unwrap();
+
+        migrate_legacy_names(&directory).unwrap();
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "???.png",
+                "dunes.jpg",
+                "hills-7.png",
+                "sunny-day-2.png",
+                "sunny-day-2.png.json"
+            ]
+        );
+
+        // nothing left to do the next time
+        migrate_legacy_names(&directory).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 5);
+    }
+
+    #[test]
+    fn legacy_stems_need_a_usable_title() {
+        assert_eq!(legacy_stem("Sunny Day"), Some("sunny-day".to_owned()));
+        assert_eq!(legacy_stem("???"), None);
+        assert_eq!(legacy_stem(""), None);
+    }
+
+    #[test]
+    fn a_missing_folder_has_nothing_to_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        migrate_legacy_names(&dir.path().join("missing").to_string_lossy()).unwrap();
+    }
+}
@@ -0,0 +1,546 @@
+use super::{
+    cached, download, safe_filename, source_directory, write_sidecar, Wallpaper, WallpaperSource,
+};
+use crate::config::UnsplashConfig;
+use crate::shuffle::ShuffleBag;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+
+pub const URL_UNSPLASH: &str = "https://api.unsplash.com";
+pub const UNSPLASH_ORIENTATIONS: &[&str] = &["landscape", "portrait", "squarish"];
+// Photos picked at random at once when shuffling, the API allows up to 30
+const UNSPLASH_SHUFFLE_BATCH: u32 = 10;
+// Unsplash asks for these on every link back to the site
+const UTM: &str = "utm_source=rusty-wallpaper&utm_medium=referral";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Urls {
+    raw: String,
+    full: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Links {
+    html: String,
+    #[serde(default)]
+    download_location: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    name: String,
+    links: Links,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Photo {
+    id: String,
+    description: Option<String>,
+    alt_description: Option<String>,
+    urls: Urls,
+    links: Links,
+    user: User,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResults {
+    total: u32,
+    results: Vec<Photo>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Errors {
+    errors: Vec<String>,
+}
+
+pub struct UnsplashSource {
+    pub config: UnsplashConfig,
+    client: reqwest::Client,
+}
+
+impl UnsplashSource {
+    pub fn new(config: &UnsplashConfig) -> ApplicationResult<UnsplashSource> {
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept-Version", HeaderValue::from_static("v1"));
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Client-ID {}", config.access_key)).map_err(|_| {
+                ApplicationError::ConfigError {
+                    e: "The Unsplash access key contains invalid characters".to_owned(),
+                }
+            })?,
+        );
+        Ok(UnsplashSource {
+            config: config.clone(),
+            client: reqwest::Client::builder()
+                .default_headers(headers)
+                .build()?,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.url.trim_end_matches('/'), path)
+    }
+
+    async fn get(
+        &self,
+        url: &str,
+        params: &[(&str, String)],
+    ) -> ApplicationResult<reqwest::Response> {
+        let response = self.client.get(url).query(params).send().await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let text = response.text().await?;
+        let message = match serde_json::from_str::<Errors>(&text) {
+            Ok(errors) => errors.errors.join(", "),
+            Err(_) => text,
+        };
+        Err(ApplicationError::HttpError {
+            status: status.as_u16(),
+            e: format!("Unsplash answered {}: {}", status, message),
+        })
+    }
+
+    // The indexable listing matching the filters: search results when there
+    // is a query, the first collection, or the whole editorial feed.
+    async fn page(&self, index: u32) -> ApplicationResult<(u32, Option<Photo>)> {
+        let mut params = vec![
+            ("page", (index + 1).to_string()),
+            ("per_page", "1".to_owned()),
+        ];
+        if let Some(orientation) = &self.config.orientation {
+            params.push(("orientation", orientation.clone()));
+        }
+
+        if let Some(query) = &self.config.query {
+            params.push(("query", query.clone()));
+            let text = self
+                .get(&self.url("/search/photos"), &params)
+                .await?
+                .text()
+                .await?;
+            let results = serde_json::from_str::<SearchResults>(&text)?;
+            return Ok((results.total, results.results.into_iter().next()));
+        }
+
+        let url = match self.config.collections.first() {
+            Some(collection) => self.url(&format!("/collections/{}/photos", collection)),
+            None => self.url("/photos"),
+        };
+        let response = self.get(&url, &params).await?;
+        let total = response
+            .headers()
+            .get("X-Total")
+            .and_then(|total| total.to_str().ok())
+            .and_then(|total| total.parse().ok())
+            .unwrap_or_default();
+        let photos = serde_json::from_str::<Vec<Photo>>(&response.text().await?)?;
+        Ok((total, photos.into_iter().next()))
+    }
+
+    fn to_wallpaper(&self, photo: &Photo) -> Wallpaper {
+        Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: Some(photo.id.clone()),
+            title: photo
+                .description
+                .clone()
+                .or_else(|| photo.alt_description.clone()),
+            creator: Some(photo.user.name.clone()),
+            permalink: Some(format!("{}?{}", photo.links.html, UTM)),
+            url: Some(photo.urls.full.clone()),
+            attribution: Some(format!(
+                "Photo by {} ({}?{}) on Unsplash",
+                photo.user.name, photo.user.links.html, UTM
+            )),
+        }
+    }
+
+    // The random endpoint is the only one honoring every filter at once
+    fn random_params(&self) -> Vec<(&str, String)> {
+        let mut params = Vec::new();
+        if let Some(query) = &self.config.query {
+            params.push(("query", query.clone()));
+        }
+        if !self.config.collections.is_empty() {
+            params.push(("collections", self.config.collections.join(",")));
+        }
+        if let Some(orientation) = &self.config.orientation {
+            params.push(("orientation", orientation.clone()));
+        }
+        if self.config.featured {
+            params.push(("featured", "true".to_owned()));
+        }
+        params
+    }
+
+    fn stem(&self, photo: &Photo, dir: &str) -> ApplicationResult<String> {
+        let title = self.to_wallpaper(photo).title;
+        Ok(source_directory(dir, &self.config.folder)?
+            + &safe_filename(title.as_deref(), &photo.id))
+    }
+
+    async fn save(&self, photo: &Photo, dir: &str) -> ApplicationResult<Wallpaper> {
+        let mut wallpaper = self.to_wallpaper(photo);
+        let stem = self.stem(photo, dir)?;
+        match cached(&stem) {
+            Some(filename) => wallpaper.path = filename,
+            None => {
+                // required by the API guidelines whenever a photo is downloaded
+                if let Some(download_location) = &photo.links.download_location {
+                    self.get(download_location, &[]).await?;
+                }
+                wallpaper.path = download(&photo.urls.full, &stem).await?;
+                write_sidecar(&wallpaper)?;
+            }
+        }
+        Ok(wallpaper)
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for UnsplashSource {
+    fn name(&self) -> &'static str {
+        "unsplash"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.page(0).await?.0)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        match self.page(index).await? {
+            (_, Some(photo)) => Ok(self.to_wallpaper(&photo)),
+            (total, None) => Err(ApplicationError::ApiError {
+                e: format!(
+                    "Unsplash photo {} doesn't exist, there are {}",
+                    index, total
+                ),
+            }),
+        }
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        match self.page(index).await? {
+            (_, Some(photo)) => self.save(&photo, dir).await,
+            (total, None) => Err(ApplicationError::ApiError {
+                e: format!(
+                    "Unsplash photo {} doesn't exist, there are {}",
+                    index, total
+                ),
+            }),
+        }
+    }
+
+    // The random endpoint has no order to go through, so a batch of photos
+    // is picked instead and the first one not downloaded yet is kept. Once
+    // most matching photos are cached, repeats come back.
+    async fn fetch_shuffled(
+        &self,
+        _bag: &mut ShuffleBag,
+        dir: &str,
+    ) -> ApplicationResult<Wallpaper> {
+        let mut params = self.random_params();
+        params.push(("count", UNSPLASH_SHUFFLE_BATCH.to_string()));
+        let text = self
+            .get(&self.url("/photos/random"), &params)
+            .await?
+            .text()
+            .await?;
+        let photos = serde_json::from_str::<Vec<Photo>>(&text)?;
+        let mut unseen = None;
+        for photo in &photos {
+            if cached(&self.stem(photo, dir)?).is_none() {
+                unseen = Some(photo);
+                break;
+            }
+        }
+        match unseen.or_else(|| photos.first()) {
+            Some(photo) => self.save(photo, dir).await,
+            None => Err(ApplicationError::ApiError {
+                e: "No photo matches the Unsplash filters".to_owned(),
+            }),
+        }
+    }
+
+    async fn fetch_random(&self, dir: &str) -> ApplicationResult<Wallpaper> {
+        let text = self
+            .get(&self.url("/photos/random"), &self.random_params())
+            .await?
+            .text()
+            .await?;
+        let photo = serde_json::from_str::<Photo>(&text)?;
+        self.save(&photo, dir).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use mockito::{Matcher, Server};
+    use std::fs;
+
+    // Trimmed from a real answer of the API, links point at the mock server
+    fn photo_json(server: &str) -> String {
+        format!(
+            r##"{{
+                "id": "Dwu85P9SOIk",
+                "created_at": "2016-05-03T11:00:28-04:00",
+                "width": 2448,
+                "height": 3264,
+                "color": "#6E633A",
+                "description": "Misty lake at dawn",
+                "alt_description": "body of water near trees",
+                "urls": {{
+                    "raw": "{server}/raw.png",
+                    "full": "{server}/full.png",
+                    "regular": "{server}/regular.png"
+                }},
+                "links": {{
+                    "self": "{server}/photos/Dwu85P9SOIk",
+                    "html": "https://unsplash.com/photos/Dwu85P9SOIk",
+                    "download": "https://unsplash.com/photos/Dwu85P9SOIk/download",
+                    "download_location": "{server}/photos/Dwu85P9SOIk/download?ixid=abc"
+                }},
+                "likes": 12,
+                "user": {{
+                    "id": "QPxL2MGqfrw",
+                    "username": "exampleuser",
+                    "name": "Joe Example",
+                    "links": {{
+                        "self": "{server}/users/exampleuser",
+                        "html": "https://unsplash.com/@exampleuser"
+                    }}
+                }}
+            }}"##
+        )
+    }
+
+    fn source(server: &Server, config: UnsplashConfig) -> UnsplashSource {
+        UnsplashSource::new(&UnsplashConfig {
+            url: server.url(),
+            access_key: "KEY".to_owned(),
+            ..config
+        })
+        .unwrap()
+    }
+
+    fn png() -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(2, 2)
+            .write_to(&mut bytes, image::ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[tokio::test]
+    async fn page_reads_search_results() {
+        let mut server = Server::new_async().await;
+        let search = server
+            .mock("GET", "/search/photos")
+            .match_header("authorization", "Client-ID KEY")
+            .match_header("accept-version", "v1")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("page".into(), "3".into()),
+                Matcher::UrlEncoded("per_page".into(), "1".into()),
+                Matcher::UrlEncoded("query".into(), "misty lake".into()),
+                Matcher::UrlEncoded("orientation".into(), "landscape".into()),
+            ]))
+            .with_body(format!(
+                r#"{{"total": 57, "total_pages": 57, "results": [{}]}}"#,
+                photo_json(&server.url())
+            ))
+            .create_async()
+            .await;
+        let source = source(
+            &server,
+            UnsplashConfig {
+                query: Some("misty lake".to_owned()),
+                ..UnsplashConfig::default()
+            },
+        );
+
+        let (total, photo) = source.page(2).await.unwrap();
+
+        search.assert_async().await;
+        assert_eq!(total, 57);
+        let wallpaper = source.to_wallpaper(&photo.unwrap());
+        assert_eq!(wallpaper.id.as_deref(), Some("Dwu85P9SOIk"));
+        assert_eq!(wallpaper.title.as_deref(), Some("Misty lake at dawn"));
+        assert_eq!(wallpaper.creator.as_deref(), Some("Joe Example"));
+        assert_eq!(
+            wallpaper.permalink.as_deref(),
+            Some("https://unsplash.com/photos/Dwu85P9SOIk?utm_source=rusty-wallpaper&utm_medium=referral")
+        );
+    }
+
+    #[tokio::test]
+    async fn page_reads_listings_with_their_total_header() {
+        let mut server = Server::new_async().await;
+        let collection = server
+            .mock("GET", "/collections/1/photos")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("page".into(), "1".into()),
+                Matcher::UrlEncoded("per_page".into(), "1".into()),
+            ]))
+            .with_header("X-Total", "12")
+            .with_body(format!("[{}]", photo_json(&server.url())))
+            .create_async()
+            .await;
+        let editorial = server
+            .mock("GET", "/photos")
+            .match_query(Matcher::UrlEncoded("page".into(), "5".into()))
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let from_collection = source(
+            &server,
+            UnsplashConfig {
+                collections: vec!["1".to_owned(), "2".to_owned()],
+                ..UnsplashConfig::default()
+            },
+        );
+        let (total, photo) = from_collection.page(0).await.unwrap();
+        assert_eq!(total, 12);
+        assert_eq!(photo.unwrap().id, "Dwu85P9SOIk");
+
+        // without the header, past the end
+        let (total, photo) = source(&server, UnsplashConfig::default())
+            .page(4)
+            .await
+            .unwrap();
+        assert_eq!(total, 0);
+        assert_eq!(photo, None);
+
+        collection.assert_async().await;
+        editorial.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn errors_of_the_api_are_reported() {
+        let mut server = Server::new_async().await;
+        let _photos = server
+            .mock("GET", "/photos")
+            .match_query(Matcher::Any)
+            .with_status(401)
+            .with_body(r#"{"errors": ["OAuth error: The access token is invalid"]}"#)
+            .create_async()
+            .await;
+
+        match source(&server, UnsplashConfig::default()).count().await {
+            Err(error @ ApplicationError::HttpError { status: 401, .. }) => {
+                assert!(!error.is_retryable());
+                assert!(
+                    error.to_string().contains("The access token is invalid"),
+                    "{}",
+                    error
+                );
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn downloads_are_tracked_once_and_credited() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let mut server = Server::new_async().await;
+        let tracking = server
+            .mock("GET", "/photos/Dwu85P9SOIk/download")
+            .match_query(Matcher::UrlEncoded("ixid".into(), "abc".into()))
+            .match_header("authorization", "Client-ID KEY")
+            .with_body(r#"{"url": "https://images.unsplash.com/photo"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let image = server
+            .mock("GET", "/full.png")
+            .with_header("content-type", "image/png")
+            .with_body(png())
+            .expect(1)
+            .create_async()
+            .await;
+        let source = source(&server, UnsplashConfig::default());
+        let photo = serde_json::from_str::<Photo>(&photo_json(&server.url())).unwrap();
+
+        let wallpaper = source.save(&photo, &dir).await.unwrap();
+        // already cached the second time
+        let again = source.save(&photo, &dir).await.unwrap();
+
+        tracking.assert_async().await;
+        image.assert_async().await;
+        assert_eq!(
+            wallpaper.path,
+            format!("{}/Unsplash/misty-lake-at-dawn-dwu85p9soik.png", dir)
+        );
+        assert_eq!(again.path, wallpaper.path);
+        let sidecar = serde_json::from_str::<Wallpaper>(
+            &fs::read_to_string(wallpaper.path.clone() + ".json").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(sidecar, wallpaper);
+        assert_eq!(
+            sidecar.attribution.as_deref(),
+            Some("Photo by Joe Example (https://unsplash.com/@exampleuser?utm_source=rusty-wallpaper&utm_medium=referral) on Unsplash")
+        );
+    }
+
+    #[tokio::test]
+    async fn shuffling_prefers_photos_not_downloaded_yet() {
+        testing::isolate();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().to_string_lossy();
+        let mut server = Server::new_async().await;
+        let seen = photo_json(&server.url());
+        let unseen = seen.replace("Dwu85P9SOIk", "Xo4xwvtT0Cs");
+        let random = server
+            .mock("GET", "/photos/random")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("query".into(), "lake".into()),
+                Matcher::UrlEncoded("count".into(), UNSPLASH_SHUFFLE_BATCH.to_string()),
+            ]))
+            .with_body(format!("[{}, {}]", seen, unseen))
+            .expect(2)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/photos/Xo4xwvtT0Cs/download")
+            .match_query(Matcher::Any)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/full.png")
+            .with_body(png())
+            .create_async()
+            .await;
+        let source = source(
+            &server,
+            UnsplashConfig {
+                query: Some("lake".to_owned()),
+                ..UnsplashConfig::default()
+            },
+        );
+        let mut bag = ShuffleBag::new(1);
+        let cached = format!("{}/Unsplash/misty-lake-at-dawn-dwu85p9soik.png", dir);
+        fs::create_dir_all(format!("{}/Unsplash", dir)).unwrap();
+        fs::write(&cached, png()).unwrap();
+
+        let wallpaper = source.fetch_shuffled(&mut bag, &dir).await.unwrap();
+        assert_eq!(
+            wallpaper.path,
+            format!("{}/Unsplash/misty-lake-at-dawn-xo4xwvtt0cs.png", dir)
+        );
+        // every photo picked is cached, one of them comes back
+        let again = source.fetch_shuffled(&mut bag, &dir).await.unwrap();
+        assert_eq!(again.path, cached);
+        random.assert_async().await;
+    }
+}
@@ -0,0 +1,350 @@
+use super::{download, safe_filename, source_directory, stable_hash, Wallpaper, WallpaperSource};
+use crate::config::WallhavenConfig;
+use crate::{ApplicationError, ApplicationResult};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+pub const URL_WALLHAVEN: &str = "https://wallhaven.cc/api/v1";
+pub const WALLHAVEN_CATEGORIES: &[&str] = &["general", "anime", "people"];
+pub const WALLHAVEN_PURITIES: &[&str] = &["sfw", "sketchy", "nsfw"];
+pub const WALLHAVEN_SORTINGS: &[&str] = &[
+    "date_added",
+    "relevance",
+    "random",
+    "views",
+    "favorites",
+    "toplist",
+];
+pub const WALLHAVEN_TOP_RANGES: &[&str] = &["1d", "3d", "1w", "1M", "3M", "6M", "1y"];
+const DEFAULT_PER_PAGE: u32 = 24;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Meta {
+    current_page: u32,
+    last_page: u32,
+    per_page: serde_json::Value,
+    total: u32,
+    #[serde(default)]
+    seed: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Data {
+    id: String,
+    url: String,
+    path: String,
+    resolution: String,
+    category: String,
+    purity: String,
+    #[serde(default)]
+    source: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchResult {
+    data: Vec<Data>,
+    meta: Meta,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    error: String,
+}
+
+// Turns a list of enabled names into wallhaven's bit flags, e.g. "110"
+fn flags(all: &[&str], enabled: &[String]) -> String {
+    all.iter()
+        .map(|name| {
+            if enabled.iter().any(|enabled| enabled == name) {
+                '1'
+            } else {
+                '0'
+            }
+        })
+        .collect()
+}
+
+// The seed of random sorting when none is configured. It comes from the
+// configuration so that the order, and the shuffled indices pointing into
+// it, survive restarts.
+fn derived_seed(config: &WallhavenConfig) -> ApplicationResult<String> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let mut hash = stable_hash(&serde_json::to_string(config)?);
+    Ok((0..6)
+        .map(|_| {
+            let c = ALPHABET[(hash % ALPHABET.len() as u64) as usize] as char;
+            hash /= ALPHABET.len() as u64;
+            c
+        })
+        .collect())
+}
+
+// wallhaven sends it as a string depending on the account settings
+fn parse_per_page(per_page: &serde_json::Value) -> u32 {
+    match per_page {
+        serde_json::Value::Number(number) => number.as_u64().map(|n| n as u32),
+        serde_json::Value::String(string) => string.parse().ok(),
+        _ => None,
+    }
+    .filter(|per_page| *per_page > 0)
+    .unwrap_or(DEFAULT_PER_PAGE)
+}
+
+// The page, counted from 1, and the position in it of wallpaper `offset`
+fn locate(offset: u32, per_page: u32) -> (u32, usize) {
+    (offset / per_page + 1, (offset % per_page) as usize)
+}
+
+pub struct WallhavenSource {
+    pub config: WallhavenConfig,
+    pub total_count: u32,
+    pub per_page: u32,
+    // random sorting is only stable across pages for the same seed
+    seed: Option<String>,
+}
+
+impl WallhavenSource {
+    pub async fn new(config: &WallhavenConfig) -> ApplicationResult<WallhavenSource> {
+        let seed = match (&config.seed, config.sorting.as_str()) {
+            (Some(seed), _) => Some(seed.clone()),
+            (None, "random") => Some(derived_seed(config)?),
+            (None, _) => None,
+        };
+        let mut source = WallhavenSource {
+            config: config.clone(),
+            total_count: 0,
+            per_page: DEFAULT_PER_PAGE,
+            seed,
+        };
+        let first_page = source.get_wallpaper_list(1).await?;
+        source.total_count = first_page.meta.total;
+        source.per_page = parse_per_page(&first_page.meta.per_page);
+        Ok(source)
+    }
+
+    async fn get_wallpaper_list(&self, page: u32) -> ApplicationResult<SearchResult> {
+        let config = &self.config;
+        let mut params = vec![
+            (
+                "categories",
+                flags(WALLHAVEN_CATEGORIES, &config.categories),
+            ),
+            ("purity", flags(WALLHAVEN_PURITIES, &config.purity)),
+            ("sorting", config.sorting.clone()),
+            ("page", page.to_string()),
+        ];
+        if let Some(query) = &config.query {
+            params.push(("q", query.clone()));
+        }
+        if !config.ratios.is_empty() {
+            params.push(("ratios", config.ratios.join(",")));
+        }
+        if let Some(atleast) = &config.atleast {
+            params.push(("atleast", atleast.clone()));
+        }
+        if config.sorting == "toplist" {
+            params.push(("topRange", config.top_range.clone()));
+        }
+        if let Some(seed) = &self.seed {
+            params.push(("seed", seed.clone()));
+        }
+        if let Some(api_key) = &config.api_key {
+            params.push(("apikey", api_key.clone()));
+        }
+
+        let response = reqwest::Client::new()
+            .get(format!("{}/search", config.url.trim_end_matches('/')))
+            .query(&params)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            let message = match serde_json::from_str::<ErrorBody>(&text) {
+                Ok(body) => body.error,
+                Err(_) => status.to_string(),
+            };
+            return Err(ApplicationError::HttpError {
+                status: status.as_u16(),
+                e: format!("Wallhaven answered {}: {}", status, message),
+            });
+        }
+        serde_json::from_str::<SearchResult>(&text)
+            .map_err(|ref e| ApplicationError::DeserializationError { e: e.to_string() })
+    }
+
+    // Same idea as the offset of simpledesktops, split into pages
+    async fn get_wallpaper(&self, offset: u32) -> ApplicationResult<Data> {
+        let (page, position) = locate(offset, self.per_page);
+        self.get_wallpaper_list(page)
+            .await?
+            .data
+            .into_iter()
+            .nth(position)
+            .ok_or_else(|| ApplicationError::ApiError {
+                e: format!("Wallhaven has no wallpaper at offset {}", offset),
+            })
+    }
+
+    fn to_wallpaper(&self, data: &Data) -> Wallpaper {
+        Wallpaper {
+            path: String::new(),
+            source: Some(self.name().to_owned()),
+            id: Some(data.id.clone()),
+            title: Some(format!(
+                "{} {} ({})",
+                data.category, data.id, data.resolution
+            )),
+            creator: None,
+            permalink: Some(data.url.clone()),
+            url: Some(data.path.clone()),
+            attribution: data.source.clone().filter(|source| !source.is_empty()),
+        }
+    }
+}
+
+#[async_trait]
+impl WallpaperSource for WallhavenSource {
+    fn name(&self) -> &'static str {
+        "wallhaven"
+    }
+
+    async fn count(&self) -> ApplicationResult<u32> {
+        Ok(self.total_count)
+    }
+
+    async fn metadata(&self, index: u32) -> ApplicationResult<Wallpaper> {
+        Ok(self.to_wallpaper(&self.get_wallpaper(index).await?))
+    }
+
+    async fn fetch(&self, index: u32, dir: &str) -> ApplicationResult<Wallpaper> {
+        let data = self.get_wallpaper(index).await?;
+        let mut wallpaper = self.to_wallpaper(&data);
+        let directory = source_directory(dir, &self.config.folder)?;
+        // the title is made of the id and resolution already
+        let stem = directory + &safe_filename(None, &format!("wallhaven-{}", data.id));
+        wallpaper.path = download(&data.path, &stem).await?;
+        Ok(wallpaper)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_follow_the_order_of_all() {
+        let enabled = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(flags(WALLHAVEN_PURITIES, &enabled(&["sfw"])), "100");
+        assert_eq!(
+            flags(WALLHAVEN_CATEGORIES, &enabled(&["people", "general"])),
+            "101"
+        );
+        assert_eq!(flags(WALLHAVEN_CATEGORIES, &[]), "000");
+        assert_eq!(flags(WALLHAVEN_PURITIES, &enabled(&["unknown"])), "000");
+    }
+
+    #[test]
+    fn per_page_can_be_a_number_or_a_string() {
+        assert_eq!(parse_per_page(&serde_json::json!(64)), 64);
+        assert_eq!(parse_per_page(&serde_json::json!("32")), 32);
+        assert_eq!(parse_per_page(&serde_json::json!("many")), DEFAULT_PER_PAGE);
+        assert_eq!(parse_per_page(&serde_json::json!(0)), DEFAULT_PER_PAGE);
+        assert_eq!(parse_per_page(&serde_json::Value::Null), DEFAULT_PER_PAGE);
+    }
+
+    #[test]
+    fn offsets_are_split_into_pages() {
+        assert_eq!(locate(0, 24), (1, 0));
+        assert_eq!(locate(23, 24), (1, 23));
+        assert_eq!(locate(24, 24), (2, 0));
+        assert_eq!(locate(100, 32), (4, 4));
+    }
+
+    #[test]
+    fn the_random_seed_comes_from_the_configuration() {
+        let config = WallhavenConfig {
+            sorting: "random".to_owned(),
+            ..WallhavenConfig::default()
+        };
+        let seed = derived_seed(&config).unwrap();
+        assert_eq!(seed.len(), 6);
+        assert!(seed.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(derived_seed(&config.clone()).unwrap(), seed);
+        let other = WallhavenConfig {
+            query: Some("lake".to_owned()),
+            ..config
+        };
+        assert_ne!(derived_seed(&other).unwrap(), seed);
+    }
+
+    #[tokio::test]
+    async fn wallpapers_are_read_from_their_page() {
+        let mut server = mockito::Server::new_async().await;
+        let page = |number: u32, ids: &[&str]| {
+            let data: Vec<_> = ids
+                .iter()
+                .map(|id| {
+                    serde_json::json!({
+                        "id": id,
+                        "url": format!("https://wallhaven.cc/w/{}", id),
+                        "path": format!("https://w.wallhaven.cc/full/{}.jpg", id),
+                        "resolution": "1920x1080",
+                        "category": "general",
+                        "purity": "sfw",
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "data": data,
+                "meta": {"current_page": number, "last_page": 2, "per_page": "2", "total": 3}
+            })
+            .to_string()
+        };
+        let seed = derived_seed(&WallhavenConfig {
+            url: server.url(),
+            sorting: "random".to_owned(),
+            ..WallhavenConfig::default()
+        })
+        .unwrap();
+        for (number, ids) in [(1, &["a1", "b2"][..]), (2, &["c3"][..])] {
+            server
+                .mock("GET", "/search")
+                .match_query(mockito::Matcher::AllOf(vec![
+                    mockito::Matcher::UrlEncoded("page".into(), number.to_string()),
+                    mockito::Matcher::UrlEncoded("sorting".into(), "random".into()),
+                    mockito::Matcher::UrlEncoded("seed".into(), seed.clone()),
+                ]))
+                .with_body(page(number, ids))
+                .create_async()
+                .await;
+        }
+        let source = WallhavenSource::new(&WallhavenConfig {
+            url: server.url(),
+            sorting: "random".to_owned(),
+            ..WallhavenConfig::default()
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(source.count().await.unwrap(), 3);
+        assert_eq!(source.per_page, 2);
+        let wallpaper = source.metadata(2).await.unwrap();
+        assert_eq!(wallpaper.id.as_deref(), Some("c3"));
+        assert_eq!(
+            wallpaper.url.as_deref(),
+            Some("https://w.wallhaven.cc/full/c3.jpg")
+        );
+        assert_eq!(wallpaper.title.as_deref(), Some("general c3 (1920x1080)"));
+        assert_eq!(source.metadata(1).await.unwrap().id.as_deref(), Some("b2"));
+        assert!(matches!(
+            source.metadata(3).await,
+            Err(ApplicationError::ApiError { .. })
+        ));
+    }
+}
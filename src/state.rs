@@ -0,0 +1,70 @@
+use crate::dirs;
+use crate::source::Wallpaper;
+use crate::ApplicationResult;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CURRENT_FILENAME: &str = "current.json";
+pub const FAVORITES_FILENAME: &str = "favorites.json";
+pub const BANNED_FILENAME: &str = "banned.json";
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurrentWallpaper {
+    #[serde(flatten)]
+    pub wallpaper: Wallpaper,
+    // seconds since the unix epoch
+    pub applied_at: u64,
+}
+
+fn current_path() -> ApplicationResult<String> {
+    Ok(dirs::state_dir()? + "/" + CURRENT_FILENAME)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn save_current(wallpaper: &Wallpaper) -> ApplicationResult<()> {
+    fs::create_dir_all(dirs::state_dir()?)?;
+    let current = CurrentWallpaper {
+        wallpaper: wallpaper.clone(),
+        applied_at: now(),
+    };
+    fs::write(current_path()?, serde_json::to_string_pretty(&current)?)?;
+    Ok(())
+}
+
+pub fn load_current() -> ApplicationResult<Option<CurrentWallpaper>> {
+    match fs::read_to_string(current_path()?) {
+        Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn load_list(filename: &str) -> ApplicationResult<Vec<Wallpaper>> {
+    match fs::read_to_string(dirs::state_dir()? + "/" + filename) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Returns false if the wallpaper was already in the list
+pub fn add_to_list(filename: &str, wallpaper: &Wallpaper) -> ApplicationResult<bool> {
+    let mut list = load_list(filename)?;
+    if list.iter().any(|listed| listed.key() == wallpaper.key()) {
+        return Ok(false);
+    }
+    list.push(wallpaper.clone());
+    fs::create_dir_all(dirs::state_dir()?)?;
+    fs::write(
+        dirs::state_dir()? + "/" + filename,
+        serde_json::to_string_pretty(&list)?,
+    )?;
+    Ok(true)
+}
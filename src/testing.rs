@@ -0,0 +1,38 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> i32;
+}
+
+// statics are never dropped, so the directory is removed when the process exits
+extern "C" fn remove_root() {
+    if let Some(root) = ROOT.get() {
+        let _ = fs::remove_dir_all(root);
+    }
+}
+
+// Points the cache, state and config directories of the whole test process
+// at a directory of its own, so tests never touch the real ones. Call it
+// before anything reads them.
+pub fn isolate() -> PathBuf {
+    ROOT.get_or_init(|| {
+        let root = tempfile::Builder::new()
+            .prefix("rusty-wallpaper-tests-")
+            .tempdir()
+            .expect("cannot create the test directory")
+            .into_path();
+        env::set_var("XDG_CACHE_HOME", root.join("cache"));
+        env::set_var("XDG_STATE_HOME", root.join("state"));
+        env::set_var("XDG_CONFIG_HOME", root.join("config"));
+        unsafe {
+            atexit(remove_root);
+        }
+        root
+    })
+    .clone()
+}
@@ -0,0 +1,216 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{is_running, wait_for, Sandbox};
+use std::fs;
+
+// An image whose path needs escaping in a URI
+fn image(sandbox: &Sandbox) -> String {
+    let path = sandbox.path("my pictures/dune #1 (été).png");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    image::RgbImage::new(4, 4).save(&path).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn gnome_sets_both_uris_and_the_options() {
+    let sandbox = Sandbox::new();
+    let log = sandbox.path("gsettings.log");
+    sandbox.stub(
+        "gsettings",
+        &format!("echo \"$@\" >> {:?}", log.to_string_lossy()),
+    );
+    sandbox.config("[image]\nmode = \"scaled\"");
+    let path = image(&sandbox);
+
+    sandbox.run(&["--backend", "gnome", "set", &path]);
+
+    let uri = format!(
+        "file://{}/my%20pictures/dune%20%231%20%28%C3%A9t%C3%A9%29.png",
+        sandbox.path("").to_string_lossy().trim_end_matches('/')
+    );
+    assert_eq!(
+        sandbox.read("gsettings.log").lines().collect::<Vec<_>>(),
+        [
+            format!("set org.gnome.desktop.background picture-uri {}", uri),
+            format!("set org.gnome.desktop.background picture-uri-dark {}", uri),
+            "set org.gnome.desktop.background picture-options scaled".to_owned(),
+        ]
+    );
+}
+
+#[test]
+fn gnome_reports_a_failing_gsettings() {
+    let sandbox = Sandbox::new();
+    sandbox.stub("gsettings", "echo 'No such schema' >&2; exit 1");
+    let path = image(&sandbox);
+
+    let output = sandbox
+        .command()
+        .args(["--backend", "gnome", "set", &path])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No such schema"));
+}
+
+#[test]
+fn kde_evaluates_a_script_on_plasmashell() {
+    let sandbox = Sandbox::new();
+    let log = sandbox.path("dbus-send.log");
+    sandbox.stub(
+        "dbus-send",
+        &format!(
+            "for arg in \"$@\"; do echo \"$arg\" >> {:?}; done",
+            log.to_string_lossy()
+        ),
+    );
+    sandbox.config("[backend]\nscreen = 0");
+    let path = image(&sandbox);
+
+    sandbox.run(&["--backend", "kde", "set", &path]);
+
+    let log = sandbox.read("dbus-send.log");
+    let args = log.lines().collect::<Vec<_>>();
+    assert_eq!(
+        args[..5],
+        [
+            "--session",
+            "--print-reply",
+            "--dest=org.kde.plasmashell",
+            "/PlasmaShell",
+            "org.kde.PlasmaShell.evaluateScript",
+        ]
+    );
+    assert!(args[5].starts_with("string:var all = desktops();"));
+    assert!(args[5].contains("if (d.screen != 0) continue;"));
+    assert!(args[5].contains("dune%20%231%20%28%C3%A9t%C3%A9%29.png"));
+}
+
+#[test]
+fn swww_gets_the_resize_and_transition_options() {
+    let sandbox = Sandbox::new();
+    let log = sandbox.path("swww.log");
+    sandbox.stub(
+        "swww",
+        &format!("echo \"$@\" >> {:?}", log.to_string_lossy()),
+    );
+    sandbox.config(
+        "[image]\nmode = \"centered\"\n\n[backend.swww]\ntransition = \"wipe\"\nduration = 1.5",
+    );
+    let path = image(&sandbox);
+
+    sandbox.run(&["--backend", "swww", "set", &path]);
+
+    assert_eq!(
+        sandbox.read("swww.log"),
+        format!(
+            "img {} --resize no --transition-type wipe --transition-duration 1.5\n",
+            path
+        )
+    );
+}
+
+#[test]
+fn swaybg_replaces_the_previous_instance() {
+    let sandbox = Sandbox::new();
+    let log = sandbox.path("swaybg.log");
+    sandbox.stub(
+        "swaybg",
+        &format!(
+            "echo \"$$ $@\" >> {:?}\nexec sleep 60",
+            log.to_string_lossy()
+        ),
+    );
+    sandbox.config("[image]\nmode = \"scaled\"");
+    let daemon = sandbox.daemon(&["--backend", "swaybg"]);
+
+    sandbox.run(&["ctl", "next"]);
+    wait_for(|| sandbox.read("swaybg.log").lines().count() == 1);
+    sandbox.run(&["ctl", "next"]);
+    wait_for(|| sandbox.read("swaybg.log").lines().count() == 2);
+
+    let log = sandbox.read("swaybg.log");
+    let instances = log
+        .lines()
+        .map(|line| line.split_once(' ').unwrap())
+        .collect::<Vec<_>>();
+    for (_, args) in &instances {
+        assert!(args.starts_with("-i "), "{}", args);
+        assert!(args.ends_with(" -m fit"), "{}", args);
+    }
+    let first = instances[0].0.parse().unwrap();
+    let second = instances[1].0.parse().unwrap();
+    assert!(!is_running(first));
+    assert!(is_running(second));
+    drop(daemon);
+    let _ = std::process::Command::new("kill")
+        .arg(second.to_string())
+        .status();
+}
+
+#[test]
+fn swaybg_keeps_the_previous_instance_when_the_new_one_fails() {
+    let sandbox = Sandbox::new();
+    let log = sandbox.path("swaybg.log");
+    sandbox.stub(
+        "swaybg",
+        &format!(
+            "echo \"$$ $@\" >> {log:?}\n\
+             if [ \"$(wc -l < {log:?})\" -gt 1 ]; then exit 1; fi\n\
+             exec sleep 60",
+            log = log.to_string_lossy()
+        ),
+    );
+    let daemon = sandbox.daemon(&["--backend", "swaybg"]);
+
+    sandbox.run(&["ctl", "next"]);
+    wait_for(|| sandbox.read("swaybg.log").lines().count() == 1);
+    // refused or retried, the first instance must not go away
+    let _ = sandbox.command().args(["ctl", "next"]).output();
+    wait_for(|| sandbox.read("swaybg.log").lines().count() >= 2);
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    let log = sandbox.read("swaybg.log");
+    let first = log.lines().next().unwrap().split_once(' ').unwrap().0;
+    let first = first.parse().unwrap();
+    assert!(is_running(first));
+    drop(daemon);
+    let _ = std::process::Command::new("kill")
+        .arg(first.to_string())
+        .status();
+}
+
+#[test]
+#[ignore = "needs an X server, e.g. xvfb-run cargo test -- --ignored"]
+fn x11_paints_the_root_window() {
+    let sandbox = Sandbox::new();
+    let display = std::env::var("DISPLAY").expect("DISPLAY is not set");
+    let path = image(&sandbox);
+
+    let output = sandbox
+        .command()
+        .env("DISPLAY", &display)
+        .args(["--backend", "x11", "set", &path])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let xprop = std::process::Command::new("xprop")
+        .args(["-root", "_XROOTPMAP_ID", "ESETROOT_PMAP_ID"])
+        .output()
+        .unwrap();
+    let properties = String::from_utf8_lossy(&xprop.stdout);
+    assert_eq!(
+        properties.matches("pixmap id #").count(),
+        2,
+        "{}",
+        properties
+    );
+}
@@ -0,0 +1,137 @@
+// Runs the real binary in a throwaway home, with stub programs in front of
+// the PATH standing in for the desktop tools.
+#![allow(dead_code)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+pub struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    pub fn new() -> Sandbox {
+        let sandbox = Sandbox {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        fs::create_dir_all(sandbox.path("bin")).unwrap();
+        sandbox.config("");
+        sandbox
+    }
+
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.dir.path().join(relative)
+    }
+
+    pub fn socket(&self) -> PathBuf {
+        self.path("daemon.sock")
+    }
+
+    // Downloads go to the sandbox, from a fake source, and `extra` is
+    // appended to the configuration.
+    pub fn config(&self, extra: &str) {
+        fs::write(
+            self.path("config.toml"),
+            format!(
+                "directory = {:?}\nsocket = {:?}\n{}\n\n[[sources]]\ntype = \"fake\"\ncount = 3\n",
+                self.path("pictures").to_string_lossy(),
+                self.socket().to_string_lossy(),
+                extra
+            ),
+        )
+        .unwrap();
+    }
+
+    // A shell script called `name` found before the real program
+    pub fn stub(&self, name: &str, script: &str) {
+        let path = self.path("bin").join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // Nothing from the environment of the test leaks in
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rusty-wallpaper"));
+        command
+            .env_clear()
+            .env("HOME", self.path("home"))
+            .env("XDG_CONFIG_HOME", self.path("config"))
+            .env("XDG_CACHE_HOME", self.path("cache"))
+            .env("XDG_STATE_HOME", self.path("state"))
+            .env("XDG_DATA_HOME", self.path("data"))
+            .env(
+                "PATH",
+                format!(
+                    "{}:{}",
+                    self.path("bin").to_string_lossy(),
+                    std::env::var("PATH").unwrap_or_default()
+                ),
+            )
+            .arg("--config")
+            .arg(self.path("config.toml"));
+        command
+    }
+
+    pub fn run(&self, args: &[&str]) -> Output {
+        let output = self.command().args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    // Waits until the daemon listens on its socket
+    pub fn daemon(&self, args: &[&str]) -> Daemon {
+        let child = self.command().args(args).arg("daemon").spawn().unwrap();
+        let daemon = Daemon { child };
+        wait_for(|| self.socket().exists());
+        daemon
+    }
+
+    pub fn read(&self, relative: &str) -> String {
+        fs::read_to_string(self.path(relative)).unwrap_or_default()
+    }
+}
+
+pub struct Daemon {
+    child: Child,
+}
+
+impl Daemon {
+    // Stops it the way a service manager would
+    pub fn terminate(mut self) -> ExitStatus {
+        let status = Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        self.child.wait().unwrap()
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+pub fn wait_for(condition: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < Duration::from_secs(10), "gave up waiting");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+pub fn is_running(pid: u32) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
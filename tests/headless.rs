@@ -0,0 +1,69 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{wait_for, Sandbox};
+use std::path::Path;
+
+#[test]
+fn daemon_applies_wallpapers_through_the_recording_backend() {
+    let sandbox = Sandbox::new();
+    sandbox.config(&format!(
+        "[backend]\nname = \"record\"\nrecord_file = {:?}",
+        sandbox.path("applied.txt").to_string_lossy()
+    ));
+    let _daemon = sandbox.daemon(&[]);
+
+    sandbox.run(&["ctl", "next"]);
+    sandbox.run(&["ctl", "next"]);
+    wait_for(|| sandbox.read("applied.txt").lines().count() == 2);
+
+    let applied = sandbox.read("applied.txt");
+    for path in applied.lines() {
+        assert!(path.starts_with(&*sandbox.path("pictures/Fake").to_string_lossy()));
+        assert!(Path::new(path).is_file(), "{} was not downloaded", path);
+    }
+    let status = String::from_utf8(sandbox.run(&["ctl", "status"]).stdout).unwrap();
+    assert!(
+        status.contains(applied.lines().last().unwrap()),
+        "{}",
+        status
+    );
+}
+
+#[test]
+fn a_stopped_daemon_ends_the_current_wallpaper() {
+    let sandbox = Sandbox::new();
+    sandbox.config(&format!(
+        "[backend]\nname = \"record\"\nrecord_file = {:?}",
+        sandbox.path("applied.txt").to_string_lossy()
+    ));
+    let daemon = sandbox.daemon(&[]);
+    sandbox.run(&["ctl", "next"]);
+    wait_for(|| sandbox.read("applied.txt").lines().count() == 1);
+
+    assert!(daemon.terminate().success());
+    let history = String::from_utf8(sandbox.run(&["history"]).stdout).unwrap();
+    assert_eq!(history.lines().count(), 1, "{}", history);
+    assert!(!history.contains("current"), "{}", history);
+}
+
+#[test]
+fn the_daemon_removes_interrupted_downloads_at_startup() {
+    let sandbox = Sandbox::new();
+    sandbox.config("[backend]\nname = \"record\"");
+    let leftovers = [
+        sandbox.path("cache/rusty-wallpaper/downloads/0123456789abcdef.part"),
+        sandbox.path("pictures/Fake/fake-1.png.part"),
+    ];
+    for leftover in &leftovers {
+        std::fs::create_dir_all(leftover.parent().unwrap()).unwrap();
+        std::fs::write(leftover, "").unwrap();
+    }
+    let kept = sandbox.path("pictures/Fake/fake-2.png");
+    std::fs::write(&kept, "").unwrap();
+
+    let _daemon = sandbox.daemon(&[]);
+    wait_for(|| leftovers.iter().all(|leftover| !leftover.exists()));
+    assert!(kept.exists());
+}